@@ -1,122 +1,632 @@
-use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
 use std::net::TcpStream;
-use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use crate::error::Error;
 
+use nn_lib::connector::auth;
+use nn_lib::connector::compression::PayloadCodec;
+use nn_lib::connector::framing::{FramedStream, DEFAULT_MAX_FRAME_SIZE};
+use nn_lib::connector::tls::{self, TlsClientFiles};
 use nn_lib::connector::messages::{
-    TcpRemoteNewMessage, TcpRequest, TcpResponce, COMPRESS_THRESHOLD, LLMP_FLAG_COMPRESSED,
-    LLMP_FLAG_INITIALIZED,
+    Capabilities, Compression, ControlCommand, Encoding, EventSubscription, FuzzerStats, NnInfo,
+    NnInputBatch, NnInputRequest, NnReward, NnTestcase, StoredKind, StoredTestcase,
+    TcpFuzzerMessage, TcpNnMessage, TcpRemoteNewMessage, TcpRequest, TcpResponce, CAP_OBSERVERS,
+    CAP_STRUCTURED, COMPRESS_THRESHOLD, LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED,
+    LLMP_TAG_EVENT_TO_BOTH, NN_INPUT_BATCH_TAG, NN_INPUT_TAG, PROTOCOL_VERSION,
 };
 
 use libafl::prelude::{EventConfig, ExitKind};
 use postcard;
-use serde::Serialize;
+use rustls::{ClientConnection, ServerName, StreamOwned};
 
 #[allow(unused)]
 use libafl::prelude::{
-    BytesInput, ClientId, Event, Flags, GzipCompressor, HasBytesVec, Input, Tag,
+    BytesInput, ClientId, Event, Flags, HasBytesVec, Input, Tag,
 };
 
 const _LLMP_NN_BLOCK_TIME: Duration = Duration::from_millis(3_000);
 
+/// Fuzzer runs on the same host unless told otherwise
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// Features supported by this client
+const CAPABILITIES: Capabilities = CAP_OBSERVERS;
+
+/// Settings of the connection to fuzzer
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// The host of fuzzer, name or IPv4 or IPv6 address
+    pub host: String,
+    /// The port nn connector of fuzzer listens to
+    pub port: u16,
+    /// Name of nn shown by fuzzer
+    pub nn_name: String,
+    /// Version of nn shown by fuzzer
+    pub nn_version: String,
+    /// Events to receive from fuzzer
+    pub subscription: EventSubscription,
+    /// Shared secret to authenticate with, if fuzzer requires it
+    pub secret: Option<Vec<u8>>,
+    /// Connect over TLS, plain tcp if `None`
+    pub tls: Option<TlsClientFiles>,
+    /// Connect to this unix socket instead of the port
+    pub unix_socket: Option<PathBuf>,
+    /// Largest frame accepted from fuzzer, the smaller limit of both sides is used.
+    /// Fuzzer refuses limits below [`nn_lib::connector::framing::MIN_MAX_FRAME_SIZE`]
+    pub max_frame_size: u32,
+    /// Receive new testcases as [`NnTestcase`] instead of libafl events
+    pub structured: bool,
+    /// How messages are serialized on the wire
+    pub encoding: Encoding,
+    /// Receive testcases stored on disk before live ones
+    pub replay: bool,
+    /// Codecs of compressed payloads we handle, most wanted first
+    pub compression: Vec<Compression>,
+    /// Payloads from this size on are compressed
+    pub compress_threshold: u32,
+}
+
+impl ConnectOptions {
+    #[must_use]
+    pub fn new(port: u16) -> Self {
+        Self {
+            host: DEFAULT_HOST.to_string(),
+            port,
+            nn_name: "markov_chain".to_string(),
+            nn_version: "1.0".to_string(),
+            subscription: EventSubscription::default(),
+            secret: None,
+            tls: None,
+            unix_socket: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            structured: false,
+            encoding: Encoding::default(),
+            replay: false,
+            compression: Compression::ALL.to_vec(),
+            compress_threshold: COMPRESS_THRESHOLD as u32,
+        }
+    }
+
+    /// Connect to fuzzer on the same host over unix socket, payloads are not compressed
+    #[must_use]
+    pub fn unix(path: &Path) -> Self {
+        Self {
+            unix_socket: Some(path.to_path_buf()),
+            compression: vec![Compression::None],
+            ..Self::new(0)
+        }
+    }
+
+    /// Features we ask the fuzzer for
+    fn capabilities(&self) -> Capabilities {
+        if self.structured {
+            CAPABILITIES | CAP_STRUCTURED
+        } else {
+            CAPABILITIES
+        }
+    }
+}
+
+/// Something forwarded by the fuzzer
+#[derive(Debug)]
+pub enum Received {
+    Event(Event<BytesInput>),
+    /// New testcase, if structured testcases are agreed on
+    Testcase(NnTestcase),
+    /// Testcase found before we connected, if replay was asked for
+    Stored(StoredTestcase),
+    /// All stored testcases are received, live ones follow
+    ReplayDone { corpus: u64, solutions: u64 },
+    /// How a fuzzer instance rated our input, see [`FuzzConnector::submit_input`]
+    Reward { fuzzer_id: ClientId, reward: NnReward },
+    /// Result of our command on one fuzzer, see [`FuzzConnector::control`].
+    /// `fuzzer_id` is `None` if the connector refused the command
+    ControlAck {
+        command_id: u64,
+        fuzzer_id: Option<ClientId>,
+        error: Option<String>,
+    },
+}
+
+/// Connection with the fuzzer
+pub enum FuzzerStream {
+    Tcp(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl FuzzerStream {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        match self {
+            FuzzerStream::Tcp(stream) => stream.set_read_timeout(dur),
+            FuzzerStream::Tls(stream) => stream.sock.set_read_timeout(dur),
+            #[cfg(unix)]
+            FuzzerStream::Unix(stream) => stream.set_read_timeout(dur),
+        }
+    }
+}
+
+impl Read for FuzzerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FuzzerStream::Tcp(stream) => stream.read(buf),
+            FuzzerStream::Tls(stream) => stream.read(buf),
+            #[cfg(unix)]
+            FuzzerStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for FuzzerStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FuzzerStream::Tcp(stream) => stream.write(buf),
+            FuzzerStream::Tls(stream) => stream.write(buf),
+            #[cfg(unix)]
+            FuzzerStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FuzzerStream::Tcp(stream) => stream.flush(),
+            FuzzerStream::Tls(stream) => stream.flush(),
+            #[cfg(unix)]
+            FuzzerStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
 pub struct FuzzConnector {
-    compressor: GzipCompressor,
+    /// Codec agreed on with the fuzzer
+    compressor: PayloadCodec,
     client_id: ClientId,
-    stream: TcpStream,
+    capabilities: Capabilities,
+    stream: FramedStream<FuzzerStream>,
+    /// Forwarded messages arrived while waiting for a reply
+    pending: VecDeque<TcpFuzzerMessage>,
+    /// Sequence number of our last ping
+    ping_seq: u64,
+    /// Id of our last submitted input
+    request_id: u64,
+    /// Id of our last control command
+    command_id: u64,
     #[allow(unused)]
     port: u16,
 }
 
 impl FuzzConnector {
     pub fn new(port: u16) -> Result<Self, Error> {
-        let (stream, client_id) = connect_to_fuzzer(port)?;
+        Self::with_options(&ConnectOptions::new(port))
+    }
+
+    /// Connect to fuzzer on the same host over unix socket
+    pub fn new_unix(path: &Path) -> Result<Self, Error> {
+        Self::with_options(&ConnectOptions::unix(path))
+    }
+
+    pub fn with_options(options: &ConnectOptions) -> Result<Self, Error> {
+        let (stream, client_id, capabilities, compressor) = connect_to_fuzzer(options)?;
 
         Ok(Self {
-            port,
+            port: options.port,
             stream,
             client_id,
-            compressor: GzipCompressor::new(COMPRESS_THRESHOLD),
+            capabilities,
+            pending: VecDeque::new(),
+            ping_seq: 0,
+            request_id: 0,
+            command_id: 0,
+            compressor,
         })
     }
 
+    /// Attach to the fuzzer as a local out-of-llmp fuzzer instead of a nn.
+    /// The fuzzer gives us the id our messages carry in llmp
+    pub fn new_local(options: &ConnectOptions) -> Result<Self, Error> {
+        let (stream, client_id, capabilities, compressor) = connect_as_local(options)?;
+
+        Ok(Self {
+            port: options.port,
+            stream,
+            client_id,
+            capabilities,
+            pending: VecDeque::new(),
+            ping_seq: 0,
+            request_id: 0,
+            command_id: 0,
+            compressor,
+        })
+    }
+
+    /// Broadcast input as a new testcase, as a fuzzer would
     pub fn send_input(&mut self, input: &[u8]) -> Result<(), Error> {
         let testcase = generate_event(self.client_id, &self.compressor, input)?;
-        send_tcp_msg(&mut self.stream, &testcase)
+        Ok(self.stream.send(&TcpNnMessage::NewMessage(testcase))?)
     }
 
-    pub fn recv_testcase(&mut self) -> Result<HashMap<String, Vec<u8>>, Error> {
-        recv_event::<BytesInput>(&mut self.stream, &self.compressor).map(|event| match event {
-            Event::NewTestcase {
-                input,
-                observers_buf,
-                ..
-            } => HashMap::from([
-                ("input".to_string(), input.bytes().to_owned()),
-                ("observers".to_string(), observers_buf.unwrap_or_default()),
-            ]),
-            _ => HashMap::from([("input".to_string(), vec![])]),
+    /// Submit input to every fuzzer instance, returns the request id.
+    /// Each instance answers with [`Received::Reward`] carrying this id
+    pub fn submit_input(&mut self, input: &[u8]) -> Result<u64, Error> {
+        self.request_id += 1;
+        let request = generate_request(self.client_id, &self.compressor, self.request_id, input)?;
+        self.stream.send(&TcpNnMessage::NewMessage(request))?;
+        Ok(self.request_id)
+    }
+
+    /// Submit inputs in one compressed message, returns their request ids in order.
+    /// The batch must fit into the frame size limit
+    pub fn submit_inputs<T: AsRef<[u8]>>(&mut self, inputs: &[T]) -> Result<Vec<u64>, Error> {
+        if inputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let first_request_id = self.request_id + 1;
+        let batch = generate_batch(self.client_id, &self.compressor, first_request_id, inputs)?;
+        self.stream.send(&TcpNnMessage::NewMessage(batch))?;
+
+        self.request_id += inputs.len() as u64;
+        Ok((first_request_id..=self.request_id).collect())
+    }
+
+    /// Change settings of `targets` fuzzer clients, all of them if `None`, returns the command id.
+    /// Each affected fuzzer answers with [`Received::ControlAck`] carrying this id
+    pub fn control(
+        &mut self,
+        command: ControlCommand,
+        targets: Option<Vec<ClientId>>,
+    ) -> Result<u64, Error> {
+        self.command_id += 1;
+        self.stream.send(&TcpNnMessage::Control {
+            command_id: self.command_id,
+            targets,
+            command,
+        })?;
+        Ok(self.command_id)
+    }
+
+    /// Change events the fuzzer forwards to us
+    pub fn subscribe(&mut self, subscription: EventSubscription) -> Result<(), Error> {
+        Ok(self.stream.send(&TcpNnMessage::Subscribe(subscription))?)
+    }
+
+    /// All nn connected to the fuzzer
+    pub fn list_nns(&mut self) -> Result<Vec<NnInfo>, Error> {
+        self.request(&TcpNnMessage::ListNns, |msg| match msg {
+            TcpFuzzerMessage::NnList(nns) => Ok(nns),
+            msg => Err(msg),
         })
     }
 
+    /// Disconnect other connection of this nn, `false` if there is no such nn.
+    /// Fuzzer refuses unless we authenticated and the nn has our name and version
+    pub fn kick_nn(&mut self, client_id: ClientId) -> Result<bool, Error> {
+        self.request(&TcpNnMessage::KickNn { client_id }, |msg| match msg {
+            TcpFuzzerMessage::Kicked { found, .. } => Ok(found),
+            msg => Err(msg),
+        })
+    }
+
+    /// Executions, corpus and objective sizes of the campaign and every client
+    pub fn stats(&mut self) -> Result<FuzzerStats, Error> {
+        self.request(&TcpNnMessage::GetStats, |msg| match msg {
+            TcpFuzzerMessage::Stats(stats) => Ok(stats),
+            msg => Err(msg),
+        })
+    }
+
+    /// Corpus or solutions entry by its file name, `None` if there is no such entry
+    pub fn corpus_entry(
+        &mut self,
+        kind: StoredKind,
+        name: &str,
+    ) -> Result<Option<StoredTestcase>, Error> {
+        let request = TcpNnMessage::GetCorpusEntry {
+            kind,
+            name: name.to_string(),
+        };
+        self.request(&request, |msg| match msg {
+            TcpFuzzerMessage::CorpusEntry {
+                kind: entry_kind,
+                name: entry_name,
+                testcase,
+            } if entry_kind == kind && entry_name == name => Ok(testcase),
+            msg => Err(msg),
+        })
+    }
+
+    /// Check the fuzzer is alive, returns round trip time
+    ///
+    /// Fuzzer drops silent nn, so call it while nn is busy for a long time
+    pub fn ping(&mut self) -> Result<Duration, Error> {
+        self.ping_seq += 1;
+        let seq = self.ping_seq;
+        let start = Instant::now();
+        self.request(&TcpNnMessage::Ping { seq }, |msg| match msg {
+            TcpFuzzerMessage::Pong { seq: pong } if pong == seq => Ok(()),
+            msg => Err(msg),
+        })?;
+        Ok(start.elapsed())
+    }
+
+    /// Receive the next forwarded event or structured testcase
+    pub fn recv(&mut self) -> Result<Received, Error> {
+        match self.recv_message()? {
+            TcpFuzzerMessage::NewMessage(msg) => {
+                decode_event(&msg, &self.compressor).map(Received::Event)
+            }
+            TcpFuzzerMessage::Testcase(testcase) => Ok(Received::Testcase(testcase)),
+            TcpFuzzerMessage::Stored(stored) => Ok(Received::Stored(stored)),
+            TcpFuzzerMessage::ReplayDone { corpus, solutions } => {
+                Ok(Received::ReplayDone { corpus, solutions })
+            }
+            TcpFuzzerMessage::Reward { fuzzer_id, reward } => {
+                Ok(Received::Reward { fuzzer_id, reward })
+            }
+            TcpFuzzerMessage::ControlAck {
+                command_id,
+                fuzzer_id,
+                error,
+            } => Ok(Received::ControlAck {
+                command_id,
+                fuzzer_id,
+                error,
+            }),
+            msg => Err(Error::illegal_state(format!("unexpected message {msg:?}"))),
+        }
+    }
+
+    /// Receive the next forwarded event, use [`Self::recv`] if testcases are structured
+    pub fn recv_event(&mut self) -> Result<Event<BytesInput>, Error> {
+        match self.recv()? {
+            Received::Event(event) => Ok(event),
+            Received::Testcase(_)
+            | Received::Stored(_)
+            | Received::ReplayDone { .. }
+            | Received::Reward { .. }
+            | Received::ControlAck { .. } => Err(Error::illegal_state(
+                "got message without event, use recv".to_string(),
+            )),
+        }
+    }
+
+    pub fn recv_testcase(&mut self) -> Result<HashMap<String, Vec<u8>>, Error> {
+        self.recv_event().map(testcase_map)
+    }
+
     #[must_use]
     pub fn id(&self) -> ClientId {
         self.client_id
     }
+
+    /// Features agreed on with the fuzzer
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Codec of compressed payloads agreed on with the fuzzer
+    #[must_use]
+    pub fn compression(&self) -> Compression {
+        self.compressor.compression()
+    }
+
+    /// Next forwarded message, anything [`Received`] is built from.
+    /// Assumed that stream has timeout enabled
+    fn recv_message(&mut self) -> Result<TcpFuzzerMessage, Error> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(msg);
+        }
+
+        loop {
+            let msg: TcpFuzzerMessage = match self.stream.recv() {
+                Ok(buf) => self.stream.encoding().decode(&buf)?,
+                Err(e) if e.is_timeout() => {
+                    return Err(Error::not_available());
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            match msg {
+                msg if is_forwarded(&msg) => return Ok(msg),
+                TcpFuzzerMessage::Error { description } => {
+                    return Err(Error::protocol_error(description));
+                }
+                TcpFuzzerMessage::Ping { seq } => {
+                    self.stream.send(&TcpNnMessage::Pong { seq })?;
+                }
+                // late reply to a request
+                _ => {}
+            }
+        }
+    }
+
+    /// Send request and wait for the reply, messages arrived meanwhile are kept
+    fn request<T>(
+        &mut self,
+        msg: &TcpNnMessage,
+        reply: impl Fn(TcpFuzzerMessage) -> Result<T, TcpFuzzerMessage>,
+    ) -> Result<T, Error> {
+        self.stream.send(msg)?;
+
+        loop {
+            let buf = self.stream.recv()?;
+            let msg: TcpFuzzerMessage = self.stream.encoding().decode(&buf)?;
+            match reply(msg) {
+                Ok(res) => return Ok(res),
+                Err(msg) if is_forwarded(&msg) => self.pending.push_back(msg),
+                Err(TcpFuzzerMessage::Error { description }) => {
+                    return Err(Error::protocol_error(description));
+                }
+                Err(TcpFuzzerMessage::Ping { seq }) => {
+                    self.stream.send(&TcpNnMessage::Pong { seq })?;
+                }
+                Err(_) => {}
+            }
+        }
+    }
 }
 
-pub fn connect_to_fuzzer(port: u16) -> Result<(TcpStream, ClientId), Error> {
-    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+pub fn connect_to_fuzzer(
+    options: &ConnectOptions,
+) -> Result<(FramedStream<FuzzerStream>, ClientId, Capabilities, PayloadCodec), Error> {
+    handshake(options, |auth| TcpRequest::RemoteNnHello {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: options.capabilities(),
+        max_frame_size: options.max_frame_size,
+        nn_name: options.nn_name.clone(),
+        nn_version: options.nn_version.clone(),
+        subscription: options.subscription.clone(),
+        replay: options.replay,
+        compression: options.compression.clone(),
+        compress_threshold: options.compress_threshold,
+        auth,
+    })
+}
 
-    // 1 - receive hello from fuzzer
-    recv_tcp_msg(&mut stream)
-        .and_then(|buf| {
-            buf.try_into()
-                .map_err(|_| Error::serialize_error("Hello message serialize error".into()))
-        })
+pub fn connect_as_local(
+    options: &ConnectOptions,
+) -> Result<(FramedStream<FuzzerStream>, ClientId, Capabilities, PayloadCodec), Error> {
+    handshake(options, |auth| TcpRequest::LocalHello {
+        protocol_version: PROTOCOL_VERSION,
+        max_frame_size: options.max_frame_size,
+        compression: options.compression.clone(),
+        compress_threshold: options.compress_threshold,
+        auth,
+    })
+}
+
+/// `hello_msg` builds our hello from the answer to fuzzer challenge
+fn handshake(
+    options: &ConnectOptions,
+    hello_msg: impl FnOnce(Option<Vec<u8>>) -> TcpRequest,
+) -> Result<(FramedStream<FuzzerStream>, ClientId, Capabilities, PayloadCodec), Error> {
+    let mut stream =
+        FramedStream::with_max_frame_size(open_stream(options)?, options.max_frame_size);
+
+    // 1 - receive hello from fuzzer, it is postcard until we choose otherwise
+    let mut buf = stream.recv()?;
+    check_protocol_version(Encoding::Postcard.peek_protocol_version(&buf))?;
+    if options.encoding != Encoding::Postcard {
+        // fuzzer repeats its hello in the encoding we name
+        stream.send_bytes(options.encoding.name().as_bytes())?;
+        stream.set_encoding(options.encoding);
+        buf = stream.recv()?;
+    }
+
+    let auth_nonce = options
+        .encoding
+        .decode(&buf)
+        .map_err(|_| Error::serialize_error("Hello message serialize error".into()))
         .and_then(|msg: TcpResponce| {
-            if let TcpResponce::RemoteFuzzerHello { .. } = msg {
-                Ok(())
+            if let TcpResponce::RemoteFuzzerHello { auth_nonce, .. } = msg {
+                Ok(auth_nonce)
             } else {
                 Err(Error::illegal_state("incorrent hello message".to_string()))
             }
         })?;
 
-    let hello_msg = TcpRequest::RemoteNnHello {
-        nn_name: "markov_chain".to_string(),
-        nn_version: "1.0".to_string(),
+    let auth = match (auth_nonce, &options.secret) {
+        (Some(nonce), Some(secret)) => Some(auth::sign(secret, &nonce)),
+        (Some(_), None) => {
+            return Err(Error::protocol_error(
+                "fuzzer requires authentication, but no secret is given".to_string(),
+            ));
+        }
+        (None, _) => None,
     };
 
     // 2 - send hello from us
-    send_tcp_msg(&mut stream, &hello_msg)?;
+    stream.send(&hello_msg(auth))?;
 
     // 3 - wait for accepting
-    let client_id = recv_tcp_msg(&mut stream)
+    let (client_id, capabilities, max_frame_size, compression, compress_threshold) = stream
+        .recv()
+        .map_err(Error::from)
         .and_then(|buf| {
-            buf.try_into()
+            options
+                .encoding
+                .decode(&buf)
                 .map_err(|_| Error::serialize_error("Accept message serialize error".into()))
         })
-        .and_then(|msg: TcpResponce| {
-            if let TcpResponce::RemoteNNAccepted { client_id } = msg {
-                Ok(client_id)
-            } else {
-                Err(Error::illegal_state(
-                    "got incorrent message while wait for accepting".to_string(),
-                ))
-            }
+        .and_then(|msg: TcpResponce| match msg {
+            TcpResponce::RemoteNNAccepted {
+                client_id,
+                capabilities,
+                max_frame_size,
+                compression,
+                compress_threshold,
+            } => Ok((
+                client_id,
+                capabilities,
+                max_frame_size,
+                compression,
+                compress_threshold,
+            )),
+            TcpResponce::LocalAccepted {
+                client_id,
+                max_frame_size,
+                compression,
+                compress_threshold,
+            } => Ok((client_id, 0, max_frame_size, compression, compress_threshold)),
+            TcpResponce::Error { description } => Err(Error::protocol_error(format!(
+                "fuzzer rejected connection: {description}"
+            ))),
+            TcpResponce::RemoteFuzzerHello { .. } => Err(Error::illegal_state(
+                "got incorrent message while wait for accepting".to_string(),
+            )),
         })?;
 
+    // fuzzer decides on the limit, both sides enforce it from now on
+    stream.set_max_frame_size(max_frame_size);
+
     // set read timeout
-    stream.set_read_timeout(Some(_LLMP_NN_BLOCK_TIME))?;
+    stream.get_ref().set_read_timeout(Some(_LLMP_NN_BLOCK_TIME))?;
 
     // return prepared stream
-    Ok((stream, client_id))
+    let compressor = PayloadCodec::new(compression, compress_threshold as usize);
+    Ok((stream, client_id, capabilities, compressor))
+}
+
+fn open_stream(options: &ConnectOptions) -> Result<FuzzerStream, Error> {
+    if let Some(path) = &options.unix_socket {
+        return open_unix_stream(path);
+    }
+
+    let stream = TcpStream::connect((options.host.as_str(), options.port))?;
+    match &options.tls {
+        Some(files) => {
+            let server_name = ServerName::try_from(files.server_name.as_str()).map_err(|_| {
+                Error::invalid_format(format!("invalid TLS server name {}", files.server_name))
+            })?;
+            let conn = ClientConnection::new(tls::client_config(files)?, server_name)
+                .map_err(|e| Error::protocol_error(format!("cannot start TLS session: {e}")))?;
+            Ok(FuzzerStream::Tls(Box::new(StreamOwned::new(conn, stream))))
+        }
+        None => Ok(FuzzerStream::Tcp(stream)),
+    }
+}
+
+#[cfg(unix)]
+fn open_unix_stream(path: &Path) -> Result<FuzzerStream, Error> {
+    Ok(FuzzerStream::Unix(UnixStream::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn open_unix_stream(_path: &Path) -> Result<FuzzerStream, Error> {
+    Err(Error::illegal_state(
+        "unix sockets are not supported on this platform".to_string(),
+    ))
 }
 
 pub fn generate_event(
     client_id: ClientId,
-    compressor: &GzipCompressor,
+    compressor: &PayloadCodec,
     buf: &[u8],
 ) -> Result<TcpRemoteNewMessage, Error> {
     let event = Event::<BytesInput>::NewTestcase {
@@ -129,19 +639,80 @@ pub fn generate_event(
         executions: 0,
     };
 
-    let serialized = postcard::to_allocvec(&event)?;
+    wrap_event(client_id, compressor, &event)
+}
+
+/// Input fuzzer instances evaluate and answer with [`NnReward`]
+pub fn generate_request(
+    client_id: ClientId,
+    compressor: &PayloadCodec,
+    request_id: u64,
+    buf: &[u8],
+) -> Result<TcpRemoteNewMessage, Error> {
+    let request = NnInputRequest {
+        request_id,
+        client_id,
+        input: buf.to_vec(),
+    };
+    let event = Event::<BytesInput>::CustomBuf {
+        tag: NN_INPUT_TAG.to_string(),
+        buf: postcard::to_allocvec(&request)?,
+    };
+
+    wrap_event(client_id, compressor, &event)
+}
+
+/// Inputs evaluated one by one, with consecutive request ids from `first_request_id`
+pub fn generate_batch<T: AsRef<[u8]>>(
+    client_id: ClientId,
+    compressor: &PayloadCodec,
+    first_request_id: u64,
+    inputs: &[T],
+) -> Result<TcpRemoteNewMessage, Error> {
+    let batch = NnInputBatch {
+        first_request_id,
+        client_id,
+        inputs: inputs.iter().map(|input| input.as_ref().to_vec()).collect(),
+    };
+    let event = Event::<BytesInput>::CustomBuf {
+        tag: NN_INPUT_BATCH_TAG.to_string(),
+        buf: postcard::to_allocvec(&batch)?,
+    };
+
+    wrap_event(client_id, compressor, &event)
+}
+
+fn check_protocol_version(version: Option<u32>) -> Result<(), Error> {
+    match version {
+        Some(PROTOCOL_VERSION) => Ok(()),
+        Some(version) => Err(Error::protocol_error(format!(
+            "fuzzer speaks protocol version {version}, client supports version {PROTOCOL_VERSION}"
+        ))),
+        None => Err(Error::protocol_error(format!(
+            "fuzzer speaks unknown protocol version, client supports version {PROTOCOL_VERSION}"
+        ))),
+    }
+}
+
+/// Message broadcasting the event over fuzzers
+fn wrap_event(
+    client_id: ClientId,
+    compressor: &PayloadCodec,
+    event: &Event<BytesInput>,
+) -> Result<TcpRemoteNewMessage, Error> {
+    let serialized = postcard::to_allocvec(event)?;
     let flags: Flags = LLMP_FLAG_INITIALIZED;
 
     let testcase = match compressor.compress(&serialized)? {
         Some(comp_buf) => TcpRemoteNewMessage {
             client_id,
-            tag: Default::default(),
+            tag: LLMP_TAG_EVENT_TO_BOTH,
             flags: flags | LLMP_FLAG_COMPRESSED,
             payload: comp_buf,
         },
         None => TcpRemoteNewMessage {
             client_id,
-            tag: Default::default(),
+            tag: LLMP_TAG_EVENT_TO_BOTH,
             flags,
             payload: serialized,
         },
@@ -150,17 +721,39 @@ pub fn generate_event(
     Ok(testcase)
 }
 
-/// Assumed that stream has timeout enabled
-pub fn recv_event<I: Input>(
-    stream: &mut TcpStream,
-    compressor: &GzipCompressor,
+/// Message fuzzer sends on its own, not a reply
+fn is_forwarded(msg: &TcpFuzzerMessage) -> bool {
+    matches!(
+        msg,
+        TcpFuzzerMessage::NewMessage(_)
+            | TcpFuzzerMessage::Testcase(_)
+            | TcpFuzzerMessage::Stored(_)
+            | TcpFuzzerMessage::ReplayDone { .. }
+            | TcpFuzzerMessage::Reward { .. }
+            | TcpFuzzerMessage::ControlAck { .. }
+    )
+}
+
+/// Input and observers of new testcase event, empty input for other events
+#[must_use]
+pub fn testcase_map(event: Event<BytesInput>) -> HashMap<String, Vec<u8>> {
+    match event {
+        Event::NewTestcase {
+            input,
+            observers_buf,
+            ..
+        } => HashMap::from([
+            ("input".to_string(), input.bytes().to_owned()),
+            ("observers".to_string(), observers_buf.unwrap_or_default()),
+        ]),
+        _ => HashMap::from([("input".to_string(), vec![])]),
+    }
+}
+
+pub fn decode_event<I: Input>(
+    msg: &TcpRemoteNewMessage,
+    compressor: &PayloadCodec,
 ) -> Result<Event<I>, Error> {
-    let msg: TcpRemoteNewMessage = match recv_tcp_msg(stream) {
-        Ok(buf) => buf.try_into()?,
-        Err(_e) => {
-            return Err(Error::not_available());
-        }
-    };
     let compressed;
 
     let event_bytes = if msg.flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
@@ -173,39 +766,3 @@ pub fn recv_event<I: Input>(
     postcard::from_bytes(event_bytes.as_slice())
         .map_err(|_e| Error::serialize_error("not Event<BytesInput> message".to_string()))
 }
-
-// helper functions
-fn send_tcp_msg<T>(stream: &mut TcpStream, msg: &T) -> Result<(), Error>
-where
-    T: Serialize,
-{
-    let msg = postcard::to_allocvec(msg)?;
-    if msg.len() > u32::MAX as usize {
-        return Err(Error::illegal_state(format!(
-            "Trying to send message a tcp message > u32! (size: {})",
-            msg.len()
-        )));
-    }
-
-    let size_bytes = (msg.len() as u32).to_be_bytes();
-    stream.write_all(&size_bytes)?;
-    stream.write_all(&msg)?;
-
-    Ok(())
-}
-
-/// Receive one message of `u32` len and `[u8; len]` bytes
-fn recv_tcp_msg(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
-    // Always receive one be u32 of size, then the command.
-
-    let mut size_bytes = [0_u8; 4];
-    stream.read_exact(&mut size_bytes)?;
-    let size = u32::from_be_bytes(size_bytes);
-    let mut bytes = vec![];
-    bytes.resize(size as usize, 0_u8);
-
-    stream
-        .read_exact(&mut bytes)
-        .expect("Failed to read message body");
-    Ok(bytes)
-}
@@ -2,6 +2,9 @@ use std::io;
 
 use serde::Deserialize;
 
+use nn_lib::connector::framing::FrameError;
+use nn_lib::connector::messages::CodecError;
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum Error {
     IOError(String),
@@ -10,6 +13,7 @@ pub enum Error {
     IllegalState(String),
     SerializeError(String),
     CompressionError(String),
+    ProtocolError(String),
 }
 
 impl std::fmt::Display for Error {
@@ -33,6 +37,9 @@ impl std::fmt::Display for Error {
             Self::NotAvailable() => {
                 writeln!(f, "Resource is unavailable")
             }
+            Self::ProtocolError(e) => {
+                writeln!(f, "Protocol error: {e}")
+            }
         }
     }
 }
@@ -69,6 +76,11 @@ impl Error {
     pub fn not_available() -> Self {
         Self::NotAvailable()
     }
+
+    #[must_use]
+    pub fn protocol_error(e: String) -> Self {
+        Self::ProtocolError(e)
+    }
     
 }
 
@@ -84,13 +96,28 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<CodecError> for Error {
+    fn from(e: CodecError) -> Self {
+        Self::serialize_error(e.to_string())
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::Io(e) => e.into(),
+            FrameError::Serialize(e) => e.into(),
+            e @ FrameError::TooLarge { .. } => Self::protocol_error(e.to_string()),
+        }
+    }
+}
+
 impl From<libafl::Error> for Error {
     fn from(e: libafl::Error) -> Self {
         match e {
             libafl::Error::Compression(_) => Self::compression_error("error while compressing buffer".to_string()),
-            _ => {
-                unreachable!()
-            }
+            libafl::Error::File(e, ..) => Self::io_error(e.to_string()),
+            e => Self::illegal_state(e.to_string()),
         }
     }
 }
\ No newline at end of file
@@ -16,16 +16,25 @@
     clippy::module_name_repetitions,
     clippy::unreadable_literal
 )]
-use pyo3::{exceptions::{PyRuntimeError, PyTimeoutError}, prelude::*};
+use pyo3::{exceptions::{PyRuntimeError, PyTimeoutError, PyValueError}, prelude::*, types::PyDict};
 #[allow(unused)]
 use pyo3::create_exception;
 
-use std::{collections::HashMap};
+use std::path::Path;
 
 pub mod connector;
 pub mod error;
 
-use connector::FuzzConnector;
+use connector::{testcase_map, ConnectOptions, FuzzConnector, Received};
+use libafl::prelude::{BytesInput, Event, HasBytesVec};
+use nn_lib::connector::auth;
+use nn_lib::connector::framing::MIN_MAX_FRAME_SIZE;
+use nn_lib::connector::tls::TlsClientFiles;
+use nn_lib::connector::messages::{
+    Compression, ControlCommand, EventSubscription, NnReward, NnTestcase, StoredKind,
+    StoredTestcase,
+};
+use nn_lib::observers::{decode_observers, ExecutionTrace};
 
 #[pyclass]
 #[repr(transparent)]
@@ -34,16 +43,177 @@ struct PyFuzzConnector(FuzzConnector);
 #[pymethods]
 impl PyFuzzConnector {
     #[new]
-    pub fn new(port: u16) -> PyResult<Self> {
-        
-        let conn = match FuzzConnector::new(port) {
-            Ok(conn) => conn,
-            Err(e) => {
-                return Err(PyErr::new::<PyRuntimeError, _>(e.to_string()));
+    #[allow(clippy::too_many_arguments)]
+    #[args(
+        kinds = "None",
+        clients = "None",
+        observers = "true",
+        name = "None",
+        version = "None",
+        secret = "None",
+        secret_file = "None",
+        max_frame_size = "None",
+        structured = "false",
+        replay = "false",
+        tls_ca = "None",
+        tls_cert = "None",
+        tls_key = "None",
+        tls_server_name = "\"localhost\"",
+        host = "None",
+        compression = "None",
+        compress_threshold = "None"
+    )]
+    pub fn new(
+        port: u16,
+        kinds: Option<Vec<String>>,
+        clients: Option<Vec<u32>>,
+        observers: bool,
+        name: Option<String>,
+        version: Option<String>,
+        secret: Option<String>,
+        secret_file: Option<String>,
+        max_frame_size: Option<u32>,
+        structured: bool,
+        replay: bool,
+        tls_ca: Option<String>,
+        tls_cert: Option<String>,
+        tls_key: Option<String>,
+        tls_server_name: String,
+        host: Option<String>,
+        compression: Option<Vec<String>>,
+        compress_threshold: Option<u32>,
+    ) -> PyResult<Self> {
+        let mut options = ConnectOptions::new(port);
+        if let Some(host) = host {
+            options.host = host;
+        }
+        fill_options(
+            &mut options,
+            kinds,
+            clients,
+            observers,
+            name,
+            version,
+            secret,
+            secret_file,
+            max_frame_size,
+            structured,
+            replay,
+            compression,
+            compress_threshold,
+        )?;
+        // TLS is enabled by the certificate fuzzer is checked against
+        options.tls = tls_ca.map(|ca| TlsClientFiles {
+            ca: ca.into(),
+            cert: tls_cert.map(Into::into),
+            key: tls_key.map(Into::into),
+            server_name: tls_server_name,
+        });
+
+        Self::connect(&options)
+    }
+
+    /// Connect to fuzzer on the same host over unix socket
+    #[staticmethod]
+    #[allow(clippy::too_many_arguments)]
+    #[args(
+        kinds = "None",
+        clients = "None",
+        observers = "true",
+        name = "None",
+        version = "None",
+        secret = "None",
+        secret_file = "None",
+        max_frame_size = "None",
+        structured = "false",
+        replay = "false",
+        compression = "None",
+        compress_threshold = "None"
+    )]
+    pub fn unix(
+        path: String,
+        kinds: Option<Vec<String>>,
+        clients: Option<Vec<u32>>,
+        observers: bool,
+        name: Option<String>,
+        version: Option<String>,
+        secret: Option<String>,
+        secret_file: Option<String>,
+        max_frame_size: Option<u32>,
+        structured: bool,
+        replay: bool,
+        compression: Option<Vec<String>>,
+        compress_threshold: Option<u32>,
+    ) -> PyResult<Self> {
+        let mut options = ConnectOptions::unix(Path::new(&path));
+        fill_options(
+            &mut options,
+            kinds,
+            clients,
+            observers,
+            name,
+            version,
+            secret,
+            secret_file,
+            max_frame_size,
+            structured,
+            replay,
+            compression,
+            compress_threshold,
+        )?;
+
+        Self::connect(&options)
+    }
+
+    /// Attach to fuzzer on the same host as a local out-of-llmp fuzzer instead of a nn,
+    /// over `port` or the unix socket at `path`
+    #[staticmethod]
+    #[args(
+        port = "None",
+        path = "None",
+        secret = "None",
+        secret_file = "None",
+        max_frame_size = "None",
+        compression = "None",
+        compress_threshold = "None"
+    )]
+    pub fn local(
+        port: Option<u16>,
+        path: Option<String>,
+        secret: Option<String>,
+        secret_file: Option<String>,
+        max_frame_size: Option<u32>,
+        compression: Option<Vec<String>>,
+        compress_threshold: Option<u32>,
+    ) -> PyResult<Self> {
+        let mut options = match (port, path) {
+            (Some(port), None) => ConnectOptions::new(port),
+            (None, Some(path)) => ConnectOptions::unix(Path::new(&path)),
+            _ => {
+                return Err(PyErr::new::<PyValueError, _>(
+                    "either port or path must be given",
+                ))
             }
         };
+        fill_options(
+            &mut options,
+            None,
+            None,
+            true,
+            None,
+            None,
+            secret,
+            secret_file,
+            max_frame_size,
+            false,
+            false,
+            compression,
+            compress_threshold,
+        )?;
 
-        Ok(Self(conn))
+        FuzzConnector::new_local(&options)
+            .map(Self)
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
     }
 
     pub fn send_input(&mut self, input: &[u8]) -> PyResult<bool> {
@@ -53,20 +223,429 @@ impl PyFuzzConnector {
         }
     }
 
-    pub fn recv_input(&mut self) -> PyResult<HashMap<String, Vec<u8>>> {
-        match self.0.recv_testcase() {
-            Ok(map) => Ok(map),
+    /// Submit input to fuzzer, returns the request id its rewards carry
+    pub fn submit_input(&mut self, input: &[u8]) -> PyResult<u64> {
+        match self.0.submit_input(input) {
+            Ok(request_id) => Ok(request_id),
+            Err(e) => Err(PyErr::new::<PyRuntimeError, _>(e.to_string())),
+        }
+    }
+
+    /// Submit inputs at once, returns their request ids in order
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn submit_inputs(&mut self, inputs: Vec<&[u8]>) -> PyResult<Vec<u64>> {
+        self.0
+            .submit_inputs(&inputs)
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// Receive the next testcase, a dict of all its fields if connected with `structured=True`.
+    /// Stored testcases and the end of replay come as dicts if connected with `replay=True`,
+    /// rewards for submitted inputs as dicts always
+    pub fn recv_input(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        match self.0.recv() {
+            Ok(Received::Testcase(testcase)) => testcase_to_dict(py, testcase),
+            Ok(Received::Stored(stored)) => stored_to_dict(py, stored),
+            Ok(Received::ReplayDone { corpus, solutions }) => {
+                replay_done_to_dict(py, corpus, solutions)
+            }
+            Ok(Received::Reward { fuzzer_id, reward }) => reward_to_dict(py, fuzzer_id, &reward),
+            Ok(Received::ControlAck {
+                command_id,
+                fuzzer_id,
+                error,
+            }) => control_ack_to_dict(py, command_id, fuzzer_id, error),
+            Ok(Received::Event(event)) => Ok(testcase_map(event).into_py(py)),
             Err(error::Error::NotAvailable()) => Err(PyErr::new::<PyTimeoutError, _>("read timeout expired")),
             Err(error::Error::SerializeError(msg)) => Err(PyErr::new::<PyTimeoutError, _>(msg)),
             Err(e) => Err(PyErr::new::<PyRuntimeError, _>(e.to_string())),
         }
     }
 
+    /// Change events the fuzzer forwards, testcases and custom buffers if `kinds` is `None`
+    #[args(kinds = "None", clients = "None", observers = "true")]
+    pub fn subscribe(
+        &mut self,
+        kinds: Option<Vec<String>>,
+        clients: Option<Vec<u32>>,
+        observers: bool,
+    ) -> PyResult<()> {
+        let subscription = make_subscription(kinds, clients, observers)?;
+        self.0
+            .subscribe(subscription)
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// Receive any subscribed event as dict with `kind` key
+    pub fn recv_event(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let event = match self.0.recv() {
+            Ok(Received::Event(event)) => event,
+            Ok(Received::Testcase(testcase)) => return testcase_to_dict(py, testcase),
+            Ok(Received::Stored(stored)) => return stored_to_dict(py, stored),
+            Ok(Received::ReplayDone { corpus, solutions }) => {
+                return replay_done_to_dict(py, corpus, solutions)
+            }
+            Ok(Received::Reward { fuzzer_id, reward }) => {
+                return reward_to_dict(py, fuzzer_id, &reward)
+            }
+            Ok(Received::ControlAck {
+                command_id,
+                fuzzer_id,
+                error,
+            }) => return control_ack_to_dict(py, command_id, fuzzer_id, error),
+            Err(error::Error::NotAvailable()) => {
+                return Err(PyErr::new::<PyTimeoutError, _>("read timeout expired"))
+            }
+            Err(e) => return Err(PyErr::new::<PyRuntimeError, _>(e.to_string())),
+        };
+        event_to_dict(py, event)
+    }
+
+    /// All nn and local fuzzers connected to the fuzzer as list of dicts
+    pub fn list_nns(&mut self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let nns = self
+            .0
+            .list_nns()
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))?;
+
+        nns.into_iter()
+            .map(|nn| {
+                let dict = PyDict::new(py);
+                dict.set_item("client_id", nn.client_id)?;
+                dict.set_item("kind", nn.kind.name())?;
+                dict.set_item("name", nn.nn_name)?;
+                dict.set_item("version", nn.nn_version)?;
+                dict.set_item("connected_at", nn.connected_at.as_secs_f64())?;
+                dict.set_item("msgs_to_nn", nn.msgs_to_nn)?;
+                dict.set_item("msgs_from_nn", nn.msgs_from_nn)?;
+                Ok(dict.into())
+            })
+            .collect()
+    }
+
+    /// Disconnect other connection of this nn, `False` if there is no such nn.
+    /// Fuzzer refuses unless connected with a secret and the nn has our name and version
+    pub fn kick_nn(&mut self, client_id: u32) -> PyResult<bool> {
+        self.0
+            .kick_nn(client_id)
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// Check the fuzzer is alive, returns round trip time in seconds.
+    /// Fuzzer drops silent nn, so call it while nn is busy for a long time
+    pub fn ping(&mut self) -> PyResult<f64> {
+        self.0
+            .ping()
+            .map(|rtt| rtt.as_secs_f64())
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
+    }
+
+    /// Campaign numbers as dict, `clients` is a list of dicts for every llmp client
+    pub fn stats(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let stats = self
+            .0
+            .stats()
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))?;
+
+        let clients = stats
+            .clients
+            .into_iter()
+            .map(|client| {
+                let dict = PyDict::new(py);
+                dict.set_item("client_id", client.client_id)?;
+                dict.set_item("executions", client.executions)?;
+                dict.set_item("execs_per_sec", client.execs_per_sec)?;
+                dict.set_item("corpus_size", client.corpus_size)?;
+                dict.set_item("objective_size", client.objective_size)?;
+                Ok(dict.into())
+            })
+            .collect::<PyResult<Vec<PyObject>>>()?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("run_time", stats.run_time.as_secs_f64())?;
+        dict.set_item("executions", stats.executions)?;
+        dict.set_item("execs_per_sec", stats.execs_per_sec)?;
+        dict.set_item("corpus_size", stats.corpus_size)?;
+        dict.set_item("objective_size", stats.objective_size)?;
+        dict.set_item("clients", clients)?;
+        Ok(dict.into())
+    }
+
+    /// Corpus or solutions entry by its file name, as `name` of stored testcases, or `None`.
+    /// `source` is `corpus` or `solution`
+    #[args(source = "\"corpus\"")]
+    pub fn corpus_entry(&mut self, py: Python<'_>, name: &str, source: &str) -> PyResult<PyObject> {
+        let kind = StoredKind::from_name(source).ok_or_else(|| {
+            PyErr::new::<PyValueError, _>(format!("unknown testcase source: {source}"))
+        })?;
+        match self.0.corpus_entry(kind, name) {
+            Ok(Some(stored)) => stored_to_dict(py, stored),
+            Ok(None) => Ok(py.None()),
+            Err(e) => Err(PyErr::new::<PyRuntimeError, _>(e.to_string())),
+        }
+    }
+
+    /// Stop fuzzing on `clients`, all fuzzers if `None`, returns the command id acks carry
+    #[args(clients = "None")]
+    pub fn pause(&mut self, clients: Option<Vec<u32>>) -> PyResult<u64> {
+        self.control(ControlCommand::Pause, clients)
+    }
+
+    /// Go on fuzzing after `pause`, returns the command id acks carry
+    #[args(clients = "None")]
+    pub fn resume(&mut self, clients: Option<Vec<u32>>) -> PyResult<u64> {
+        self.control(ControlCommand::Resume, clients)
+    }
+
+    /// Run `1..=max` mutational iterations per testcase, `max` is at most 65536.
+    /// Returns the command id acks carry
+    #[args(clients = "None")]
+    pub fn set_iterations(&mut self, max: u64, clients: Option<Vec<u32>>) -> PyResult<u64> {
+        self.control(ControlCommand::SetIterations { max }, clients)
+    }
+
+    /// Stack `2^(1..=pow)` mutations, returns the command id acks carry
+    #[args(clients = "None")]
+    pub fn set_stack_pow(&mut self, pow: u64, clients: Option<Vec<u32>>) -> PyResult<u64> {
+        self.control(ControlCommand::SetStackPow { pow }, clients)
+    }
+
     pub fn id(&self) -> u32 {
         self.0.id()
     }
+
+    /// Name of the codec agreed on with the fuzzer, "none" if payloads are not compressed
+    pub fn compression(&self) -> &'static str {
+        self.0.compression().name()
+    }
 }
 
+impl PyFuzzConnector {
+    fn control(&mut self, command: ControlCommand, clients: Option<Vec<u32>>) -> PyResult<u64> {
+        self.0
+            .control(command, clients)
+            .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
+    }
+
+    fn connect(options: &ConnectOptions) -> PyResult<Self> {
+        let conn = match FuzzConnector::with_options(options) {
+            Ok(conn) => conn,
+            Err(e) => {
+                return Err(PyErr::new::<PyRuntimeError, _>(e.to_string()));
+            }
+        };
+
+        Ok(Self(conn))
+    }
+}
+
+/// Settings shared by all transports
+#[allow(clippy::too_many_arguments)]
+fn fill_options(
+    options: &mut ConnectOptions,
+    kinds: Option<Vec<String>>,
+    clients: Option<Vec<u32>>,
+    observers: bool,
+    name: Option<String>,
+    version: Option<String>,
+    secret: Option<String>,
+    secret_file: Option<String>,
+    max_frame_size: Option<u32>,
+    structured: bool,
+    replay: bool,
+    compression: Option<Vec<String>>,
+    compress_threshold: Option<u32>,
+) -> PyResult<()> {
+    if let Some(name) = name {
+        options.nn_name = name;
+    }
+    if let Some(version) = version {
+        options.nn_version = version;
+    }
+    options.subscription = make_subscription(kinds, clients, observers)?;
+    options.secret = match (secret, secret_file) {
+        (Some(secret), _) => Some(secret.into_bytes()),
+        (None, Some(path)) => Some(
+            auth::load_secret(Path::new(&path))
+                .map_err(|e| PyErr::new::<PyValueError, _>(e.to_string()))?,
+        ),
+        (None, None) => None,
+    };
+    if let Some(max_frame_size) = max_frame_size {
+        if max_frame_size < MIN_MAX_FRAME_SIZE {
+            return Err(PyErr::new::<PyValueError, _>(format!(
+                "max_frame_size must be at least {MIN_MAX_FRAME_SIZE}"
+            )));
+        }
+        options.max_frame_size = max_frame_size;
+    }
+    options.structured = structured;
+    options.replay = replay;
+    // codec names, most wanted first
+    if let Some(names) = compression {
+        options.compression = names
+            .iter()
+            .map(|name| {
+                Compression::from_name(name).ok_or_else(|| {
+                    PyErr::new::<PyValueError, _>(format!("unknown compression: {name}"))
+                })
+            })
+            .collect::<PyResult<_>>()?;
+    }
+    if let Some(compress_threshold) = compress_threshold {
+        options.compress_threshold = compress_threshold;
+    }
+    Ok(())
+}
+
+fn make_subscription(
+    kinds: Option<Vec<String>>,
+    clients: Option<Vec<u32>>,
+    observers: bool,
+) -> PyResult<EventSubscription> {
+    let kinds = match kinds {
+        Some(names) => names.iter().try_fold(0, |kinds, name| {
+            EventSubscription::kind_from_name(name)
+                .map(|kind| kinds | kind)
+                .ok_or_else(|| PyErr::new::<PyValueError, _>(format!("unknown event kind: {name}")))
+        })?,
+        None => EventSubscription::default().kinds,
+    };
+
+    Ok(EventSubscription {
+        kinds,
+        clients,
+        observers,
+    })
+}
+
+fn event_to_dict(py: Python<'_>, event: Event<BytesInput>) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    match event {
+        Event::NewTestcase {
+            input,
+            observers_buf,
+            corpus_size,
+            executions,
+            ..
+        } => {
+            let trace = observers_buf
+                .as_deref()
+                .and_then(|buf| decode_observers(buf).ok());
+            dict.set_item("kind", "new_testcase")?;
+            dict.set_item("input", input.bytes())?;
+            dict.set_item("observers", observers_buf.unwrap_or_default())?;
+            dict.set_item("exec_time", trace.as_ref().and_then(trace_exec_time))?;
+            dict.set_item("coverage", trace.map(|trace| trace.coverage))?;
+            dict.set_item("corpus_size", corpus_size)?;
+            dict.set_item("executions", executions)?;
+        }
+        Event::UpdateExecStats { executions, .. } => {
+            dict.set_item("kind", "exec_stats")?;
+            dict.set_item("executions", executions)?;
+        }
+        Event::UpdateUserStats { name, value, .. } => {
+            dict.set_item("kind", "user_stats")?;
+            dict.set_item("name", name)?;
+            dict.set_item("value", value.to_string())?;
+        }
+        Event::Objective { objective_size } => {
+            dict.set_item("kind", "objective")?;
+            dict.set_item("objective_size", objective_size)?;
+        }
+        Event::Log { message, .. } => {
+            dict.set_item("kind", "log")?;
+            dict.set_item("message", message)?;
+        }
+        Event::CustomBuf { tag, buf } => {
+            dict.set_item("kind", "custom")?;
+            dict.set_item("tag", tag)?;
+            dict.set_item("buf", buf)?;
+        }
+    }
+    Ok(dict.into())
+}
+
+/// Structured testcase as dict with `kind` key, like new testcase events
+fn testcase_to_dict(py: Python<'_>, testcase: NnTestcase) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", "new_testcase")?;
+    dict.set_item("client_id", testcase.client_id)?;
+    dict.set_item("input", testcase.input)?;
+    dict.set_item("exit_kind", testcase.exit_kind.name())?;
+    dict.set_item("exec_time", testcase.exec_time.map(|time| time.as_secs_f64()))?;
+    dict.set_item("executions", testcase.executions)?;
+    dict.set_item("corpus_size", testcase.corpus_size)?;
+    dict.set_item("time", testcase.time.as_secs_f64())?;
+    dict.set_item("coverage", testcase.coverage)?;
+    Ok(dict.into())
+}
+
+fn trace_exec_time(trace: &ExecutionTrace) -> Option<f64> {
+    trace.exec_time.map(|time| time.as_secs_f64())
+}
+
+/// Decode `observers` of a testcase into dict of `exec_time` and `coverage`,
+/// the list of `(edge index, hit bucket)` for nonzero edges
+#[pyfunction]
+fn decode_coverage(py: Python<'_>, observers: &[u8]) -> PyResult<PyObject> {
+    let trace =
+        decode_observers(observers).map_err(|e| PyErr::new::<PyValueError, _>(e.to_string()))?;
+    let dict = PyDict::new(py);
+    dict.set_item("exec_time", trace_exec_time(&trace))?;
+    dict.set_item("coverage", trace.coverage)?;
+    Ok(dict.into())
+}
+
+/// Testcase found before nn connected, `source` is `corpus` or `solution`
+fn stored_to_dict(py: Python<'_>, stored: StoredTestcase) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", "stored_testcase")?;
+    dict.set_item("source", stored.kind.name())?;
+    dict.set_item("name", stored.name)?;
+    dict.set_item("input", stored.input)?;
+    dict.set_item("exec_time", stored.exec_time.map(|time| time.as_secs_f64()))?;
+    dict.set_item("executions", stored.executions)?;
+    dict.set_item("metadata", stored.metadata)?;
+    Ok(dict.into())
+}
+
+fn replay_done_to_dict(py: Python<'_>, corpus: u64, solutions: u64) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", "replay_done")?;
+    dict.set_item("corpus", corpus)?;
+    dict.set_item("solutions", solutions)?;
+    Ok(dict.into())
+}
+
+/// Reward for input sent with `submit_input`, one from every fuzzer instance
+fn reward_to_dict(py: Python<'_>, fuzzer_id: u32, reward: &NnReward) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", "reward")?;
+    dict.set_item("request_id", reward.request_id)?;
+    dict.set_item("fuzzer_id", fuzzer_id)?;
+    dict.set_item("exit_kind", reward.exit_kind.name())?;
+    dict.set_item("novelty", reward.novelty.name())?;
+    dict.set_item("new_edges", reward.new_edges)?;
+    dict.set_item("exec_time", reward.exec_time.map(|time| time.as_secs_f64()))?;
+    Ok(dict.into())
+}
+
+/// Result of command sent with `pause`, `resume` or `set_*`, one from every affected fuzzer.
+/// `fuzzer_id` is `None` if the connector refused the command, `error` is `None` on success
+fn control_ack_to_dict(
+    py: Python<'_>,
+    command_id: u64,
+    fuzzer_id: Option<u32>,
+    error: Option<String>,
+) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("kind", "control_ack")?;
+    dict.set_item("command_id", command_id)?;
+    dict.set_item("fuzzer_id", fuzzer_id)?;
+    dict.set_item("error", error)?;
+    Ok(dict.into())
+}
 
 /// A Python module implemented in Rust. The name of this function must match
 /// the `lib.name` setting in the `Cargo.toml`, else Python will not be able to
@@ -74,6 +653,7 @@ impl PyFuzzConnector {
 #[pymodule]
 fn nn_connector(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyFuzzConnector>()?;
+    m.add_function(wrap_pyfunction!(decode_coverage, m)?)?;
 
     Ok(())
 }
@@ -0,0 +1,260 @@
+//! Play back nn connector sessions logged with `--nn-record-dir`
+//!
+//! The log is played as the fuzzer against a nn, or as the nn against a live fuzzer.
+//! Frames of the other side are compared with the recorded ones, the playback goes on
+//! whatever they are.
+
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser, Subcommand};
+use libafl::Error;
+
+use nn_fuzz::connector::framing::FramedStream;
+use nn_fuzz::connector::messages::{
+    Encoding, TcpFuzzerMessage, TcpNnMessage, TcpRequest, TcpResponce,
+};
+use nn_fuzz::connector::record::{Direction, RecordEntry, SessionLog};
+
+/// Decoded frames longer than this are cut in dumps
+const MAX_DUMP_LEN: usize = 240;
+
+#[derive(Debug, Parser)]
+#[command(about = "Play back nn connector session logs")]
+struct Options {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print every frame of the log
+    Dump {
+        /// The session log
+        log: PathBuf,
+    },
+    /// Act as the fuzzer, wait for a nn and send it the recorded fuzzer frames
+    Fuzzer {
+        /// The session log
+        log: PathBuf,
+        /// The address nn connects to, IPv4 or IPv6
+        #[arg(long, default_value = "127.0.0.1", value_name = "ADDR")]
+        bind: IpAddr,
+        /// The port nn connects to
+        #[arg(short = 'p', long, default_value = "7878", value_name = "PORT")]
+        port: u16,
+        #[command(flatten)]
+        pace: Pace,
+    },
+    /// Act as the nn, connect to a live fuzzer and send it the recorded nn frames.
+    /// Fuzzers with a shared secret reject the recorded authentication
+    Nn {
+        /// The session log
+        log: PathBuf,
+        /// The host of the fuzzer
+        #[arg(long, default_value = "127.0.0.1", value_name = "HOST")]
+        host: String,
+        /// The port of the fuzzer
+        #[arg(short = 'p', long, default_value = "7878", value_name = "PORT")]
+        port: u16,
+        #[command(flatten)]
+        pace: Pace,
+    },
+}
+
+#[derive(Debug, Args)]
+struct Pace {
+    /// Playback speed, 2 is twice as fast as recorded, 0 sends frames without delays
+    #[arg(long, default_value = "1.0", value_parser = parse_speed, value_name = "FACTOR")]
+    speed: f64,
+    /// How long to wait for every frame of the other side (secs)
+    #[arg(long, default_value = "10", value_parser = parse_secs, value_name = "SECS")]
+    wait: Duration,
+}
+
+fn parse_speed(src: &str) -> Result<f64, String> {
+    match src.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed >= 0.0 => Ok(speed),
+        _ => Err(format!("{src} is not a non-negative number")),
+    }
+}
+
+fn parse_secs(src: &str) -> Result<Duration, Error> {
+    let secs: u64 = src.parse()?;
+    if secs == 0 {
+        return Err(Error::illegal_argument("Timeout must be positive"));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+fn main() {
+    let options = Options::parse();
+    let res = match options.command {
+        Command::Dump { log } => dump(&log),
+        Command::Fuzzer {
+            log,
+            bind,
+            port,
+            pace,
+        } => play_fuzzer(&log, SocketAddr::new(bind, port), &pace),
+        Command::Nn {
+            log,
+            host,
+            port,
+            pace,
+        } => play_nn(&log, &host, port, &pace),
+    };
+
+    if let Err(e) = res {
+        eprintln!("nn_replay: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn read_log(path: &Path) -> Result<Vec<RecordEntry>, Error> {
+    SessionLog::open(path)?.collect()
+}
+
+fn dump(path: &Path) -> Result<(), Error> {
+    let entries = read_log(path)?;
+    let first = entries.first().map(|entry| entry.time).unwrap_or_default();
+
+    // the fuzzer hello is postcard, nn may name another encoding in reply
+    let mut encoding = Encoding::Postcard;
+    let (mut to_nn, mut from_nn) = (0, 0);
+    // hello and accept to nn, request from nn, a named encoding adds a frame to both
+    let (mut handshake_to_nn, mut handshake_from_nn) = (2, 1);
+    for entry in &entries {
+        let (nth, handshake) = match entry.direction {
+            Direction::ToNn => (&mut to_nn, handshake_to_nn),
+            Direction::FromNn => (&mut from_nn, handshake_from_nn),
+        };
+        let named = match entry.direction {
+            Direction::FromNn if *nth == 0 => Encoding::from_name(&entry.frame),
+            _ => None,
+        };
+        let description = match named {
+            Some(named) => {
+                encoding = named;
+                handshake_to_nn += 1;
+                handshake_from_nn += 1;
+                format!("encoding {}", named.name())
+            }
+            None => describe(entry, encoding, *nth < handshake),
+        };
+        *nth += 1;
+
+        println!(
+            "{:>10.3}s {} {:>8} bytes  {description}",
+            entry.time.saturating_sub(first).as_secs_f64(),
+            match entry.direction {
+                Direction::ToNn => "->nn",
+                Direction::FromNn => "<-nn",
+            },
+            entry.frame.len(),
+        );
+    }
+    Ok(())
+}
+
+/// Decode frame, messages of the `handshake` differ from the ones after it
+fn describe(entry: &RecordEntry, encoding: Encoding, handshake: bool) -> String {
+    let decoded = match (entry.direction, handshake) {
+        (Direction::ToNn, true) => encoding
+            .decode::<TcpResponce>(&entry.frame)
+            .map(|msg| format!("{msg:?}")),
+        (Direction::ToNn, false) => encoding
+            .decode::<TcpFuzzerMessage>(&entry.frame)
+            .map(|msg| format!("{msg:?}")),
+        (Direction::FromNn, true) => encoding
+            .decode::<TcpRequest>(&entry.frame)
+            .map(|msg| format!("{msg:?}")),
+        (Direction::FromNn, false) => encoding
+            .decode::<TcpNnMessage>(&entry.frame)
+            .map(|msg| format!("{msg:?}")),
+    };
+
+    match decoded {
+        Ok(mut text) => {
+            if let Some((cut, _)) = text.char_indices().nth(MAX_DUMP_LEN) {
+                text.truncate(cut);
+                text.push_str("...");
+            }
+            text
+        }
+        Err(e) => format!("undecodable: {e}"),
+    }
+}
+
+fn play_fuzzer(path: &Path, addr: SocketAddr, pace: &Pace) -> Result<(), Error> {
+    let entries = read_log(path)?;
+    let listener = TcpListener::bind(addr)?;
+    println!("Waiting for nn on {addr}...");
+
+    let (stream, addr) = listener.accept()?;
+    println!("Playing {} frames to {addr}", entries.len());
+    play(stream, &entries, Direction::ToNn, pace)
+}
+
+fn play_nn(path: &Path, host: &str, port: u16, pace: &Pace) -> Result<(), Error> {
+    let entries = read_log(path)?;
+    let stream = TcpStream::connect((host, port))?;
+    println!("Playing {} frames to {host}:{port}", entries.len());
+    play(stream, &entries, Direction::FromNn, pace)
+}
+
+/// Send frames of `ours` direction in recorded time, receive one frame for each of the other
+fn play(
+    stream: TcpStream,
+    entries: &[RecordEntry],
+    ours: Direction,
+    pace: &Pace,
+) -> Result<(), Error> {
+    stream.set_read_timeout(Some(pace.wait))?;
+    let mut stream = FramedStream::with_max_frame_size(stream, u32::MAX);
+
+    let first = entries.first().map(|entry| entry.time).unwrap_or_default();
+    let start = Instant::now();
+    let (mut sent, mut same, mut different, mut missing) = (0, 0, 0, 0);
+
+    for (idx, entry) in entries.iter().enumerate() {
+        if entry.direction == ours {
+            if pace.speed > 0.0 {
+                let due = entry.time.saturating_sub(first).div_f64(pace.speed);
+                if let Some(delay) = due.checked_sub(start.elapsed()) {
+                    thread::sleep(delay);
+                }
+            }
+            stream.send_bytes(&entry.frame)?;
+            sent += 1;
+            continue;
+        }
+
+        match stream.recv() {
+            Ok(frame) if frame == entry.frame => same += 1,
+            Ok(frame) => {
+                println!(
+                    "#{idx}: received {} bytes, recorded {} bytes differ",
+                    frame.len(),
+                    entry.frame.len()
+                );
+                different += 1;
+            }
+            Err(e) if e.is_timeout() => {
+                println!("#{idx}: nothing received in {:?}", pace.wait);
+                missing += 1;
+            }
+            Err(e) => {
+                println!("#{idx}: connection lost: {e}");
+                break;
+            }
+        }
+    }
+
+    println!(
+        "Sent {sent} frames, received {same} as recorded, {different} different, {missing} missing"
+    );
+    Ok(())
+}
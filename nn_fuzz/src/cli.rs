@@ -1,3 +1,4 @@
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -8,6 +9,9 @@ use serde::{Deserialize, Serialize};
 
 use libafl::{Error, prelude::Cores};
 
+use crate::connector::framing::MIN_MAX_FRAME_SIZE;
+use crate::connector::queue::OverflowPolicy;
+use crate::connector::server::DEFAULT_BIND_ADDR;
 use crate::utils::seed::Seeds;
 
 #[must_use]
@@ -19,6 +23,14 @@ fn parse_timeout(src: &str) -> Result<Duration, Error> {
     Ok(Duration::from_millis(src.parse()?))
 }
 
+fn parse_secs(src: &str) -> Result<Duration, Error> {
+    let secs: u64 = src.parse()?;
+    if secs == 0 {
+        return Err(Error::illegal_argument("Timeout must be positive"));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about)]
 pub struct FuzzerOptions {
@@ -85,6 +97,16 @@ pub struct FuzzerOptions {
     )]
     pub timeout: Duration,
 
+    /// The size of the coverage map shared with the target, AFL++ targets need at least their
+    /// `AFL_DUMP_MAP_SIZE`
+    #[arg(
+        long,
+        default_value_t = crate::MAP_SIZE,
+        value_name = "SIZE",
+        help_heading = "Fuzz Options",
+    )]
+    pub map_size: usize,
+
     /// The token file for token mutations
     #[arg(
         short = 'x',
@@ -182,5 +204,132 @@ pub struct FuzzerOptions {
     )]
     pub client_port: u16,
 
+    /// The address nn-clients connect to, IPv4 or IPv6 (`::` may accept IPv4 too, depending on OS)
+    #[arg(
+        long,
+        default_value_t = DEFAULT_BIND_ADDR,
+        value_name = "ADDR",
+        help_heading = "Broker Options",
+    )]
+    pub client_bind: IpAddr,
+
+    /// The unix socket nn-clients connect to instead of the port
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "tls_cert",
+        help_heading = "Broker Options",
+    )]
+    pub client_socket: Option<PathBuf>,
+
+    /// The maximum number of nn-clients and local fuzzers connected at once
+    #[arg(
+        long,
+        default_value = "4",
+        value_name = "COUNT",
+        help_heading = "Broker Options",
+    )]
+    pub max_nn_clients: usize,
+
+    /// The nn-client is dropped after this long silence (secs), it is pinged three times within it
+    #[arg(
+        long,
+        value_parser = parse_secs,
+        default_value = "30",
+        value_name = "SECS",
+        help_heading = "Broker Options",
+    )]
+    pub nn_timeout: Duration,
+
+    /// The maximum number of messages waiting to be sent to each nn-client
+    #[arg(
+        long,
+        default_value = "1024",
+        value_name = "COUNT",
+        help_heading = "Broker Options",
+    )]
+    pub nn_queue_size: usize,
+
+    /// What to do with messages when nn-client is too slow
+    #[arg(
+        long,
+        value_enum,
+        default_value = "block",
+        value_name = "POLICY",
+        help_heading = "Broker Options",
+    )]
+    pub nn_overflow: OverflowPolicy,
+
+    /// The largest message accepted from nn-client (bytes), the smaller limit of both sides is used
+    #[arg(
+        long,
+        default_value = "16777216",
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u32).range(i64::from(MIN_MAX_FRAME_SIZE)..),
+        help_heading = "Broker Options",
+    )]
+    pub nn_max_frame: u32,
+
+    /// If set, every frame exchanged with nn-clients is logged there, one file per session.
+    /// Logs are played back with `nn_replay`
+    #[arg(
+        long,
+        value_name = "DIR",
+        help_heading = "Broker Options",
+    )]
+    pub nn_record_dir: Option<PathBuf>,
+
+    /// If set, new testcases carry only nonzero edges instead of all observers.
+    /// Needs `observer_feedback` feature
+    #[arg(
+        long,
+        help_heading = "Broker Options",
+    )]
+    pub nn_sparse_coverage: bool,
+
+    /// The shared secret nn-clients must prove to know, no authentication if unset
+    #[arg(
+        long,
+        value_name = "SECRET",
+        conflicts_with = "nn_secret_file",
+        help_heading = "Broker Options",
+    )]
+    pub nn_secret: Option<String>,
+
+    /// The file with shared secret for nn-clients
+    #[arg(
+        long,
+        value_name = "FILE",
+        help_heading = "Broker Options",
+    )]
+    pub nn_secret_file: Option<PathBuf>,
+
+    /// The PEM certificate to serve nn-clients over TLS, plain tcp if unset
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_key",
+        help_heading = "Broker Options",
+    )]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM private key of the TLS certificate
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_cert",
+        help_heading = "Broker Options",
+    )]
+    pub tls_key: Option<PathBuf>,
+
+    /// The PEM CA nn-client certificates must be signed by, clients are not verified if unset
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_cert",
+        help_heading = "Broker Options",
+    )]
+    pub tls_client_ca: Option<PathBuf>,
+
 
 }
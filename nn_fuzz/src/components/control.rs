@@ -0,0 +1,165 @@
+use core::time::Duration;
+
+use libafl::{
+    bolts::current_time,
+    events::{CustomBufEventResult, Event, EventFirer, EventProcessor, ProgressReporter},
+    fuzzer::STATS_TIMEOUT_DEFAULT,
+    inputs::UsesInput,
+    stages::StagesTuple,
+    state::{HasClientPerfMonitor, HasExecutions, HasMetadata, UsesState},
+    Error, Fuzzer, SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::connector::messages::{
+    ClientId, ControlCommand, NnControlAck, NnControlRequest, NN_CONTROL_ACK_TAG, NN_CONTROL_TAG,
+};
+
+/// How long a paused fuzzer waits before looking for new events
+pub const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Fuzzer settings changed by nn, read by the stages
+#[derive(Serialize, Deserialize, SerdeAny, Debug, Default, Clone)]
+pub struct NnControl {
+    paused: bool,
+    max_iterations: Option<u64>,
+    stack_pow: Option<u64>,
+    /// Acks waiting for the stage to send them
+    acks: Vec<NnControlAck>,
+}
+
+impl NnControl {
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Iteration budget of the mutational stage, `None` keeps the default
+    #[must_use]
+    pub fn max_iterations(&self) -> Option<u64> {
+        self.max_iterations
+    }
+
+    /// Mutation stack power, `None` keeps the one of the mutator
+    #[must_use]
+    pub fn stack_pow(&self) -> Option<u64> {
+        self.stack_pow
+    }
+
+    fn apply(&mut self, command: ControlCommand) {
+        match command {
+            ControlCommand::Pause => self.paused = true,
+            ControlCommand::Resume => self.paused = false,
+            ControlCommand::SetIterations { max } => self.max_iterations = Some(max),
+            ControlCommand::SetStackPow { pow } => self.stack_pow = Some(pow),
+        }
+    }
+}
+
+/// Custom buffer handler, applies [`NnControlRequest`] addressed to `client_id` to [`NnControl`].
+/// Event handlers cannot fire events, the ack waits for [`send_control_acks`]
+///
+/// # Errors
+///    if buffer tagged [`NN_CONTROL_TAG`] does not hold [`NnControlRequest`]
+///
+#[allow(clippy::ptr_arg)]
+pub fn apply_nn_control<S: HasMetadata>(
+    state: &mut S,
+    tag: &String,
+    buf: &[u8],
+    client_id: ClientId,
+) -> Result<CustomBufEventResult, Error> {
+    if tag != NN_CONTROL_TAG {
+        return Ok(CustomBufEventResult::Next);
+    }
+
+    let request: NnControlRequest = postcard::from_bytes(buf)?;
+    if let Some(targets) = request.targets.as_ref() {
+        if !targets.contains(&client_id) {
+            return Ok(CustomBufEventResult::Handled);
+        }
+    }
+
+    if !state.has_metadata::<NnControl>() {
+        state.add_metadata(NnControl::default());
+    }
+    if let Some(control) = state.metadata_mut().get_mut::<NnControl>() {
+        // connector checks too, this covers nn talking to llmp directly
+        let error = request.command.validate().err();
+        if error.is_none() {
+            control.apply(request.command);
+        }
+        control.acks.push(NnControlAck {
+            command_id: request.command_id,
+            client_id: request.client_id,
+            error,
+        });
+    }
+    Ok(CustomBufEventResult::Handled)
+}
+
+/// Send acks of the commands applied since the last call
+///
+/// # Errors
+///    if an ack cannot be sent
+///
+pub fn send_control_acks<EM, S>(state: &mut S, manager: &mut EM) -> Result<(), Error>
+where
+    EM: UsesState<State = S> + EventFirer,
+    S: UsesInput + HasMetadata,
+{
+    let acks = match state.metadata_mut().get_mut::<NnControl>() {
+        Some(control) if !control.acks.is_empty() => std::mem::take(&mut control.acks),
+        _ => return Ok(()),
+    };
+
+    for ack in acks {
+        manager.fire(
+            state,
+            Event::CustomBuf {
+                tag: NN_CONTROL_ACK_TAG.to_string(),
+                buf: postcard::to_allocvec(&ack)?,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Fuzz like [`Fuzzer::fuzz_loop`], but a fuzzer paused by nn runs no stage,
+/// it only processes events until nn resumes it. Acks are sent either way
+///
+/// # Errors
+///    if fuzzing fails, ``Error::ShuttingDown`` when fuzzer stops normally
+///
+pub fn fuzz_loop<E, EM, ST, Z>(
+    fuzzer: &mut Z,
+    stages: &mut ST,
+    executor: &mut E,
+    state: &mut Z::State,
+    manager: &mut EM,
+) -> Result<(), Error>
+where
+    Z: Fuzzer<E, EM, ST>,
+    Z::State: HasClientPerfMonitor + HasExecutions + HasMetadata,
+    E: UsesState<State = Z::State>,
+    EM: ProgressReporter<State = Z::State> + EventProcessor<E, Z> + EventFirer,
+    ST: StagesTuple<E, EM, Z::State, Z>,
+{
+    let mut last = current_time();
+    loop {
+        send_control_acks(state, manager)?;
+        if nn_control(state).map_or(false, NnControl::is_paused) {
+            // resume comes as an event like any other command
+            manager.process(fuzzer, state, executor)?;
+            std::thread::sleep(PAUSE_POLL_INTERVAL);
+        } else {
+            fuzzer.fuzz_one(stages, executor, state, manager)?;
+        }
+        last = manager.maybe_report_progress(state, last, STATS_TIMEOUT_DEFAULT)?;
+    }
+}
+
+/// Settings of nn, `None` until nn sent the first command
+pub fn nn_control<S: HasMetadata>(state: &S) -> Option<&NnControl> {
+    state.metadata().get::<NnControl>()
+}
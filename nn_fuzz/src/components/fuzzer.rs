@@ -17,6 +17,8 @@ use libafl::{
 };
 use serde::{de::DeserializeOwned, Serialize};
 
+use super::observers::ExecutionTrace;
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct HeavyFuzzer<CS, F, OF, OT>
@@ -29,6 +31,8 @@ where
     scheduler: CS,
     feedback: F,
     objective: OF,
+    /// Send nonzero edges instead of all observers with new testcases
+    sparse_coverage: bool,
     phantom: PhantomData<OT>,
 }
 
@@ -167,7 +171,7 @@ where
                 let idx = state.corpus_mut().add(testcase)?;
                 self.scheduler_mut().on_add(state, idx)?;
 
-                let observers_buf = Some(manager.serialize_observers(observers)?);
+                let observers_buf = Some(self.observers_buf(manager, observers)?);
 
                 if send_events {
                     manager.fire(
@@ -347,10 +351,31 @@ where
             scheduler,
             feedback,
             objective,
+            sparse_coverage: false,
             phantom: PhantomData,
         }
     }
 
+    /// Attach [`ExecutionTrace`] of nonzero edges to new testcases instead of
+    /// serialized observers, see [`super::observers::decode_observers`]
+    pub fn set_sparse_coverage(&mut self, sparse_coverage: bool) {
+        self.sparse_coverage = sparse_coverage;
+    }
+
+    /// Observers as sent with new testcase, sparse trace if enabled and edges are observed
+    fn observers_buf<EM>(&self, manager: &mut EM, observers: &OT) -> Result<Vec<u8>, Error>
+    where
+        EM: EventFirer<State = CS::State>,
+        OT: ObserversTuple<CS::State> + Serialize,
+    {
+        if self.sparse_coverage {
+            if let Some(trace) = ExecutionTrace::from_observers(observers) {
+                return trace.encode();
+            }
+        }
+        manager.serialize_observers(observers)
+    }
+
     /// Runs the input and triggers observers and feedback
     pub fn execute_input<E, EM>(
         &mut self,
@@ -1,2 +1,6 @@
+pub mod control;
 pub mod fuzzer;
+pub mod mutators;
+pub mod nn_inputs;
+pub mod observers;
 pub mod stages;
\ No newline at end of file
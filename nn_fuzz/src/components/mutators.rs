@@ -0,0 +1,102 @@
+use core::fmt::{self, Debug};
+use core::marker::PhantomData;
+
+use libafl::{
+    bolts::{rands::Rand, tuples::HasConstLen},
+    mutators::{
+        ComposedByMutations, MutationId, MutationResult, Mutator, MutatorsTuple, ScheduledMutator,
+    },
+    state::{HasMetadata, HasRand},
+    Error,
+};
+
+use super::control::nn_control;
+
+/// [`libafl::mutators::StdScheduledMutator`] whose stack power can be changed by nn
+/// with [`crate::connector::messages::ControlCommand::SetStackPow`]
+pub struct ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    mutations: MT,
+    max_stack_pow: u64,
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<I, MT, S> ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    /// `max_stack_pow` is used until nn sets its own
+    pub fn with_max_stack_pow(mutations: MT, max_stack_pow: u64) -> Self {
+        Self {
+            mutations,
+            max_stack_pow,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, MT, S> Debug for ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ControlledScheduledMutator with {} mutations for Input type {}",
+            self.mutations.len(),
+            core::any::type_name::<I>()
+        )
+    }
+}
+
+impl<I, MT, S> Mutator<I, S> for ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut I,
+        stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        self.scheduled_mutate(state, input, stage_idx)
+    }
+}
+
+impl<I, MT, S> ComposedByMutations<I, MT, S> for ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn mutations(&self) -> &MT {
+        &self.mutations
+    }
+
+    fn mutations_mut(&mut self) -> &mut MT {
+        &mut self.mutations
+    }
+}
+
+impl<I, MT, S> ScheduledMutator<I, MT, S> for ControlledScheduledMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn iterations(&self, state: &mut S, _: &I) -> u64 {
+        let pow = nn_control(state)
+            .and_then(|control| control.stack_pow())
+            .unwrap_or(self.max_stack_pow);
+        1 << (1 + state.rand_mut().below(pow))
+    }
+
+    fn schedule(&self, state: &mut S, _: &I) -> MutationId {
+        debug_assert!(!self.mutations.is_empty());
+        state.rand_mut().below(self.mutations.len() as u64).into()
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+
+use libafl::{
+    events::{CustomBufEventResult, Event, EventFirer},
+    executors::HasObservers,
+    feedbacks::{MapFeedbackMetadata, MAPFEEDBACK_PREFIX},
+    fuzzer::{ExecuteInputResult, ExecutesInput},
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    state::{
+        HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata, HasNamedMetadata,
+        HasSolutions, UsesState,
+    },
+    Error, ExecutionProcessor, SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+
+use super::observers::{ExecutionTrace, EDGES_OBSERVER_NAME};
+use crate::connector::messages::{
+    NnInputBatch, NnInputRequest, NnReward, Novelty, NN_INPUT_BATCH_TAG, NN_INPUT_TAG,
+    NN_REWARD_TAG,
+};
+
+/// Inputs of nn waiting to be evaluated by the stage
+#[derive(Serialize, Deserialize, SerdeAny, Debug, Default, Clone)]
+pub struct NnInputs {
+    pending: VecDeque<NnInputRequest>,
+}
+
+/// Custom buffer handler, queues [`NnInputRequest`] for [`evaluate_nn_inputs`],
+/// [`NnInputBatch`] is unpacked into requests.
+/// Event handlers cannot run the target, so evaluation waits for the next stage
+///
+/// # Errors
+///    if buffer tagged [`NN_INPUT_TAG`] or [`NN_INPUT_BATCH_TAG`] does not hold its message
+///
+#[allow(clippy::ptr_arg)]
+pub fn queue_nn_input<S: HasMetadata>(
+    state: &mut S,
+    tag: &String,
+    buf: &[u8],
+) -> Result<CustomBufEventResult, Error> {
+    let requests = match tag.as_str() {
+        NN_INPUT_TAG => vec![postcard::from_bytes::<NnInputRequest>(buf)?],
+        NN_INPUT_BATCH_TAG => postcard::from_bytes::<NnInputBatch>(buf)?
+            .into_requests()
+            .collect(),
+        _ => return Ok(CustomBufEventResult::Next),
+    };
+
+    if !state.has_metadata::<NnInputs>() {
+        state.add_metadata(NnInputs::default());
+    }
+    if let Some(inputs) = state.metadata_mut().get_mut::<NnInputs>() {
+        inputs.pending.extend(requests);
+    }
+    Ok(CustomBufEventResult::Handled)
+}
+
+/// Run queued nn inputs and answer each one with [`NnReward`]
+///
+/// Every fuzzer instance gets the input, so interesting ones are not announced again.
+///
+/// # Errors
+///    if target cannot be run or the reward cannot be sent
+///
+pub fn evaluate_nn_inputs<E, EM, Z, OT>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut Z::State,
+    manager: &mut EM,
+) -> Result<(), Error>
+where
+    E: UsesState<State = Z::State> + HasObservers<Observers = OT>,
+    EM: UsesState<State = Z::State> + EventFirer,
+    OT: ObserversTuple<Z::State> + Serialize,
+    Z: ExecutesInput<E, EM> + ExecutionProcessor<OT>,
+    Z::State: HasClientPerfMonitor
+        + HasCorpus
+        + HasSolutions
+        + HasExecutions
+        + HasMetadata
+        + HasNamedMetadata,
+    <Z::State as UsesInput>::Input: From<Vec<u8>>,
+{
+    let pending = match state.metadata_mut().get_mut::<NnInputs>() {
+        Some(inputs) if !inputs.pending.is_empty() => std::mem::take(&mut inputs.pending),
+        _ => return Ok(()),
+    };
+
+    for request in pending {
+        let input = request.input.into();
+        let exit_kind = fuzzer.execute_input(state, executor, manager, &input)?;
+        let observers = executor.observers();
+
+        // history is updated by feedback, look before that
+        let trace = ExecutionTrace::from_observers(observers);
+        let new_edges = trace
+            .as_ref()
+            .map_or(0, |trace| count_new_edges(state, &trace.coverage));
+
+        let (res, _) =
+            fuzzer.process_execution(state, manager, input, observers, &exit_kind, false)?;
+
+        let reward = NnReward {
+            request_id: request.request_id,
+            client_id: request.client_id,
+            exit_kind: (&exit_kind).into(),
+            novelty: match res {
+                ExecuteInputResult::None => Novelty::None,
+                ExecuteInputResult::Corpus => Novelty::Corpus,
+                ExecuteInputResult::Solution => Novelty::Solution,
+            },
+            new_edges,
+            exec_time: trace.and_then(|trace| trace.exec_time),
+        };
+        manager.fire(
+            state,
+            Event::CustomBuf {
+                tag: NN_REWARD_TAG.to_string(),
+                buf: postcard::to_allocvec(&reward)?,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Edges the map feedback has never seen, all of them before the first feedback run
+fn count_new_edges<S: HasNamedMetadata>(state: &S, coverage: &[(u32, u8)]) -> u64 {
+    let name = format!("{MAPFEEDBACK_PREFIX}{EDGES_OBSERVER_NAME}");
+    let history = state
+        .named_metadata()
+        .get::<MapFeedbackMetadata<u8>>(&name)
+        .map(|meta| meta.history_map.as_slice());
+
+    coverage
+        .iter()
+        .filter(|&&(idx, _)| {
+            history
+                .and_then(|history| history.get(idx as usize))
+                .map_or(true, |&hits| hits == 0)
+        })
+        .count() as u64
+}
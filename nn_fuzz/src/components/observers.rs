@@ -0,0 +1,82 @@
+use core::time::Duration;
+
+use libafl::bolts::tuples::MatchName;
+use libafl::observers::{HitcountsMapObserver, MapObserver, StdMapObserver, TimeObserver};
+use libafl::Error;
+use serde::{Deserialize, Serialize};
+
+/// Edge coverage map of the target, hit counts are classified into buckets
+pub type EdgesObserver = HitcountsMapObserver<StdMapObserver<'static, u8, false>>;
+
+/// Observers of the fuzzer executor, in the order they are serialized into `observers_buf`
+pub type FuzzObservers = (TimeObserver, (EdgesObserver, ()));
+
+/// Name of [`EdgesObserver`] in [`FuzzObservers`]
+pub const EDGES_OBSERVER_NAME: &str = "edges";
+
+/// Name of [`TimeObserver`] in [`FuzzObservers`]
+pub const TIME_OBSERVER_NAME: &str = "time";
+
+/// Starts `observers_buf` holding a sparse [`ExecutionTrace`] instead of [`FuzzObservers`].
+/// Serialized observers start with the length of the first observer name, never zero
+const SPARSE_TRACE_MAGIC: &[u8; 4] = b"\0nsc";
+
+/// What the nn needs from the observers of one execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionTrace {
+    pub exec_time: Option<Duration>,
+    /// Nonzero edges as `(edge index, hit bucket)`
+    pub coverage: Vec<(u32, u8)>,
+}
+
+impl ExecutionTrace {
+    /// Take the trace from observers of [`FuzzObservers`] names, `None` without edges observer
+    pub fn from_observers<OT: MatchName>(observers: &OT) -> Option<Self> {
+        let edges = observers.match_name::<EdgesObserver>(EDGES_OBSERVER_NAME)?;
+        let exec_time = observers
+            .match_name::<TimeObserver>(TIME_OBSERVER_NAME)
+            .and_then(|time| *time.last_runtime());
+
+        Some(Self {
+            exec_time,
+            coverage: sparse_edges(edges),
+        })
+    }
+
+    /// Serialize as compact `observers_buf`, much smaller than the whole map
+    ///
+    /// # Errors
+    ///    if postcard fails to serialize
+    ///
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = SPARSE_TRACE_MAGIC.to_vec();
+        buf.extend(postcard::to_allocvec(self)?);
+        Ok(buf)
+    }
+}
+
+/// Decode `observers_buf` of a new testcase event, either sparse or full observers
+///
+/// # Errors
+///    if the buffer holds neither [`ExecutionTrace`] nor [`FuzzObservers`]
+///
+pub fn decode_observers(observers_buf: &[u8]) -> Result<ExecutionTrace, Error> {
+    if let Some(sparse) = observers_buf.strip_prefix(SPARSE_TRACE_MAGIC.as_slice()) {
+        return Ok(postcard::from_bytes(sparse)?);
+    }
+
+    let (time, (edges, ())): FuzzObservers = postcard::from_bytes(observers_buf)?;
+    Ok(ExecutionTrace {
+        exec_time: *time.last_runtime(),
+        coverage: sparse_edges(&edges),
+    })
+}
+
+/// Nonzero entries of the map with their index
+fn sparse_edges(edges: &EdgesObserver) -> Vec<(u32, u8)> {
+    (0..edges.usable_count())
+        .map(|idx| (idx, *edges.get(idx)))
+        .filter(|&(_, hits)| hits != 0)
+        .filter_map(|(idx, hits)| u32::try_from(idx).ok().map(|idx| (idx, hits)))
+        .collect()
+}
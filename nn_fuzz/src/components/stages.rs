@@ -21,14 +21,17 @@ use libafl::{
     stages::Stage,
     start_timer,
     state::{
-        HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata, HasRand, HasSolutions,
-        UsesState,
+        HasClientPerfMonitor, HasCorpus, HasExecutions, HasMetadata, HasNamedMetadata, HasRand,
+        HasSolutions, UsesState,
     },
     Error, ExecutionProcessor, SerdeAny,
 };
 
 use serde::{Deserialize, Serialize};
 
+use super::control::nn_control;
+use super::nn_inputs::evaluate_nn_inputs;
+
 pub trait MutationalStage<E, EM, M, Z, OT>: Stage<E, EM, Z>
 where
     E: UsesState<State = Self::State> + HasObservers<Observers = OT>,
@@ -142,9 +145,17 @@ where
     EM: UsesState<State = Z::State> + EventFirer,
     OT: ObserversTuple<Z::State> + Serialize,
     Z: ExecutesInput<E, EM> + ExecutionProcessor<OT>,
-    Z::State:
-        HasClientPerfMonitor + HasCorpus + HasSolutions + HasExecutions + HasRand + HasMetadata,
+    Z::State: HasClientPerfMonitor
+        + HasCorpus
+        + HasSolutions
+        + HasExecutions
+        + HasRand
+        + HasMetadata
+        + HasNamedMetadata,
+    Z::Input: From<Vec<u8>>,
 {
+    /// Inputs submitted by nn go first, they wait for their rewards.
+    /// Pausing is up to [`super::control::fuzz_loop`], paused fuzzer never gets here
     fn perform(
         &mut self,
         fuzzer: &mut Z,
@@ -153,6 +164,7 @@ where
         manager: &mut EM,
         corpus_idx: CorpusId,
     ) -> Result<(), Error> {
+        evaluate_nn_inputs(fuzzer, executor, state, manager)?;
         self.perform_mutational(fuzzer, executor, state, manager, corpus_idx)
     }
 }
@@ -176,7 +188,10 @@ where
     }
 
     fn iterations(&self, state: &mut <Z>::State, _corpus_idx: CorpusId) -> Result<usize, Error> {
-        Ok(1 + state.rand_mut().below(DEFAULT_MUTATIONAL_MAX_ITERATIONS) as usize)
+        let max = nn_control(state)
+            .and_then(|control| control.max_iterations())
+            .unwrap_or(DEFAULT_MUTATIONAL_MAX_ITERATIONS);
+        Ok(1 + state.rand_mut().below(max) as usize)
     }
 
     fn perform_mutational(
@@ -0,0 +1,97 @@
+//! Shared secret authentication of nn connections
+//!
+//! The fuzzer sends random nonce in its hello, the peer answers with
+//! HMAC-SHA256 of the nonce keyed by the shared secret.
+use std::fs;
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use libafl::Error;
+use sha2::Sha256;
+
+/// Length of the fuzzer challenge
+pub const NONCE_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Fresh random challenge for one connection
+///
+/// # Errors
+///    if system random source is unavailable
+pub fn new_nonce() -> Result<Vec<u8>, Error> {
+    let mut nonce = vec![0_u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce)
+        .map_err(|e| Error::unknown(format!("Cannot generate auth nonce: {e}")))?;
+    Ok(nonce)
+}
+
+/// Answer the challenge of fuzzer
+#[must_use]
+pub fn sign(secret: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let mut mac = new_mac(secret);
+    mac.update(nonce);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Check the answer of peer in constant time
+#[must_use]
+pub fn verify(secret: &[u8], nonce: &[u8], answer: &[u8]) -> bool {
+    let mut mac = new_mac(secret);
+    mac.update(nonce);
+    mac.verify_slice(answer).is_ok()
+}
+
+/// Read the shared secret from file, trailing newline is ignored
+///
+/// # Errors
+///    if file cannot be read or is empty
+pub fn load_secret(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut secret = fs::read(path)?;
+    while matches!(secret.last(), Some(b'\n' | b'\r')) {
+        secret.pop();
+    }
+    if secret.is_empty() {
+        return Err(Error::illegal_argument(format!(
+            "Shared secret file {} is empty",
+            path.display()
+        )));
+    }
+    Ok(secret)
+}
+
+fn new_mac(secret: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length
+    <HmacSha256 as Mac>::new_from_slice(secret).unwrap_or_else(|_| unreachable!())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_nonce, sign, verify, NONCE_LEN};
+
+    #[test]
+    fn signed_nonce_verifies() {
+        let nonce = new_nonce().unwrap();
+        assert_eq!(nonce.len(), NONCE_LEN);
+
+        let answer = sign(b"secret", &nonce);
+        assert!(verify(b"secret", &nonce, &answer));
+    }
+
+    #[test]
+    fn wrong_secret_is_rejected() {
+        let nonce = new_nonce().unwrap();
+        let answer = sign(b"other secret", &nonce);
+        assert!(!verify(b"secret", &nonce, &answer));
+    }
+
+    #[test]
+    fn answer_to_other_nonce_is_rejected() {
+        let answer = sign(b"secret", &new_nonce().unwrap());
+        assert!(!verify(b"secret", &new_nonce().unwrap(), &answer));
+        // truncated answers too
+        let nonce = new_nonce().unwrap();
+        let answer = sign(b"secret", &nonce);
+        assert!(!verify(b"secret", &nonce, &answer[..answer.len() - 1]));
+        assert!(!verify(b"secret", &nonce, &[]));
+    }
+}
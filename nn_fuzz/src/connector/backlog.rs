@@ -0,0 +1,146 @@
+use core::time::Duration;
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use super::messages::{StoredKind, StoredTestcase, TcpFuzzerMessage};
+
+/// Part of `.<testcase>.metadata` files written by on-disk corpora
+#[derive(Deserialize)]
+struct StoredMetadata {
+    exec_time: Option<Duration>,
+    executions: Option<u64>,
+}
+
+/// Testcases stored on disk before the nn connected, sent before live messages
+#[derive(Debug)]
+pub(crate) struct Backlog {
+    pending: VecDeque<(StoredKind, PathBuf)>,
+    corpus: u64,
+    solutions: u64,
+}
+
+impl Backlog {
+    /// List testcases in the order they were found, missing directories are just empty
+    pub(crate) fn scan(corpus_dir: Option<&Path>, solutions_dir: Option<&Path>) -> Self {
+        let mut files: Vec<_> = [
+            (StoredKind::Corpus, corpus_dir),
+            (StoredKind::Solution, solutions_dir),
+        ]
+        .into_iter()
+        .filter_map(|(kind, dir)| dir.map(|dir| (kind, dir)))
+        .flat_map(|(kind, dir)| {
+            list_testcases(dir)
+                .into_iter()
+                .map(move |(found, path)| (found, kind, path))
+        })
+        .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+
+        Self {
+            pending: files.into_iter().map(|(_, kind, path)| (kind, path)).collect(),
+            corpus: 0,
+            solutions: 0,
+        }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Tells nn that live messages follow
+    pub(crate) fn done(&self) -> TcpFuzzerMessage {
+        TcpFuzzerMessage::ReplayDone {
+            corpus: self.corpus,
+            solutions: self.solutions,
+        }
+    }
+}
+
+impl Iterator for Backlog {
+    type Item = StoredTestcase;
+
+    /// Testcases removed by fuzzer since the scan are skipped
+    fn next(&mut self) -> Option<StoredTestcase> {
+        while let Some((kind, path)) = self.pending.pop_front() {
+            let stored = match read_stored(kind, &path) {
+                Some(stored) => stored,
+                None => continue,
+            };
+
+            match kind {
+                StoredKind::Corpus => self.corpus += 1,
+                StoredKind::Solution => self.solutions += 1,
+            }
+            return Some(stored);
+        }
+        None
+    }
+}
+
+/// Testcase with file name `name` in `dir`, see [`StoredTestcase::name`].
+/// Names of other directories, metadata and locks are refused
+pub(crate) fn find_stored(kind: StoredKind, dir: &Path, name: &str) -> Option<StoredTestcase> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.starts_with('.') => {}
+        _ => return None,
+    }
+    let path = dir.join(name);
+    if !path.is_file() {
+        return None;
+    }
+    read_stored(kind, &path)
+}
+
+/// Testcase file with its `.<name>.metadata`, `None` if it cannot be read
+pub(crate) fn read_stored(kind: StoredKind, path: &Path) -> Option<StoredTestcase> {
+    let input = fs::read(path).ok()?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let metadata_path = path.with_file_name(format!(".{name}.metadata"));
+    let metadata = fs::read_to_string(metadata_path).ok();
+    let parsed = metadata
+        .as_deref()
+        .and_then(|text| serde_json::from_str::<StoredMetadata>(text).ok());
+
+    Some(StoredTestcase {
+        kind,
+        name,
+        input,
+        exec_time: parsed.as_ref().and_then(|meta| meta.exec_time),
+        executions: parsed.as_ref().and_then(|meta| meta.executions),
+        metadata,
+    })
+}
+
+/// Testcase files with their modification time in the order they were found,
+/// hidden files are metadata and locks
+fn list_testcases(dir: &Path) -> Vec<(SystemTime, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            if !meta.is_file() {
+                return None;
+            }
+            Some((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), entry.path()))
+        })
+        .collect();
+    // same time, the name decides
+    files.sort();
+    files
+}
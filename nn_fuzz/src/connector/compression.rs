@@ -0,0 +1,290 @@
+//! Compression of llmp payloads on nn connections
+//!
+//! Inside llmp a payload flagged with [`LLMP_FLAG_COMPRESSED`] is gzip, see [`COMPRESS_THRESHOLD`].
+//! On a nn connection it is in the [`Compression`] agreed on in handshake,
+//! the fuzzer side [`transcode`]s every forwarded message between the two.
+
+use std::io::Read;
+
+use libafl::bolts::compress::GzipCompressor;
+use libafl::Error;
+
+use super::messages::{Compression, TcpRemoteNewMessage, COMPRESS_THRESHOLD, LLMP_FLAG_COMPRESSED};
+
+/// Larger payloads are refused, a small frame must not make us allocate gigabytes
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Zstd level, favours speed over ratio
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec and threshold of one side of the connector
+#[derive(Debug)]
+pub struct PayloadCodec {
+    compression: Compression,
+    threshold: usize,
+    gzip: GzipCompressor,
+}
+
+impl PayloadCodec {
+    /// Payloads from `threshold` bytes on are compressed with `compression`
+    #[must_use]
+    pub fn new(compression: Compression, threshold: usize) -> Self {
+        Self {
+            compression,
+            threshold,
+            gzip: GzipCompressor::new(threshold),
+        }
+    }
+
+    /// The codec of llmp messages
+    #[must_use]
+    pub fn llmp() -> Self {
+        Self::new(Compression::Gzip, COMPRESS_THRESHOLD)
+    }
+
+    #[must_use]
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    #[must_use]
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Compressed payload, `None` if it is below the threshold or nothing is compressed
+    ///
+    /// # Errors
+    ///    if the codec fails
+    ///
+    pub fn compress(&self, buf: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if buf.len() < self.threshold {
+            return Ok(None);
+        }
+        match self.compression {
+            Compression::None => Ok(None),
+            Compression::Gzip => self.gzip.compress(buf),
+            Compression::Lz4 => Ok(Some(lz4_flex::compress_prepend_size(buf))),
+            Compression::Zstd => zstd::bulk::compress(buf, ZSTD_LEVEL)
+                .map(Some)
+                .map_err(|_| Error::compression()),
+        }
+    }
+
+    /// # Errors
+    ///    if payload is not compressed with the codec or is larger than [`MAX_DECOMPRESSED_SIZE`]
+    ///
+    pub fn decompress(&self, buf: &[u8]) -> Result<Vec<u8>, Error> {
+        match self.compression {
+            Compression::None => Err(Error::illegal_argument(
+                "compressed payload, but compression is off",
+            )),
+            Compression::Gzip => decompress_gzip(buf),
+            Compression::Lz4 => decompress_lz4(buf),
+            Compression::Zstd => decompress_zstd(buf),
+        }
+    }
+}
+
+/// Re-encode compressed payload of `msg` from one codec to the other.
+/// Uncompressed payload is compressed if it reaches the threshold of `to`
+///
+/// # Errors
+///    if payload cannot be decompressed or compressed, `msg` is left as it was then
+///
+pub fn transcode(
+    from: &PayloadCodec,
+    to: &PayloadCodec,
+    msg: &mut TcpRemoteNewMessage,
+) -> Result<(), Error> {
+    if msg.flags & LLMP_FLAG_COMPRESSED != LLMP_FLAG_COMPRESSED {
+        if let Some(compressed) = to.compress(&msg.payload)? {
+            msg.payload = compressed;
+            msg.flags |= LLMP_FLAG_COMPRESSED;
+        }
+        return Ok(());
+    }
+    // the receiver does not care about the threshold of the sender
+    if from.compression == to.compression {
+        return Ok(());
+    }
+
+    let raw = from.decompress(&msg.payload)?;
+    (msg.flags, msg.payload) = match to.compress(&raw)? {
+        Some(compressed) => (msg.flags, compressed),
+        None => (msg.flags & !LLMP_FLAG_COMPRESSED, raw),
+    };
+    Ok(())
+}
+
+/// Inflate of what [`GzipCompressor`] makes, it is raw deflate despite the name
+fn decompress_gzip(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut raw = vec![];
+    flate2::read::DeflateDecoder::new(buf)
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut raw)
+        .map_err(|e| Error::illegal_argument(format!("bad gzip payload: {e}")))?;
+    if raw.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(too_large(raw.len()));
+    }
+    Ok(raw)
+}
+
+fn decompress_lz4(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    // the size goes first, it is checked before anything is allocated
+    let size = match buf {
+        [a, b, c, d, ..] => u32::from_le_bytes([*a, *b, *c, *d]) as usize,
+        _ => return Err(Error::illegal_argument("truncated lz4 payload")),
+    };
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(too_large(size));
+    }
+    lz4_flex::decompress_size_prepended(buf)
+        .map_err(|e| Error::illegal_argument(format!("bad lz4 payload: {e}")))
+}
+
+fn decompress_zstd(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    let decoder = zstd::stream::read::Decoder::new(buf)
+        .map_err(|e| Error::illegal_argument(format!("bad zstd payload: {e}")))?;
+
+    let mut raw = vec![];
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut raw)
+        .map_err(|e| Error::illegal_argument(format!("bad zstd payload: {e}")))?;
+    if raw.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(too_large(raw.len()));
+    }
+    Ok(raw)
+}
+
+fn too_large(size: usize) -> Error {
+    Error::illegal_argument(format!(
+        "payload of {size} bytes exceeds the limit of {MAX_DECOMPRESSED_SIZE} bytes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use libafl::bolts::llmp::Flags;
+
+    use super::{transcode, PayloadCodec, MAX_DECOMPRESSED_SIZE};
+    use crate::connector::messages::{
+        Compression, TcpRemoteNewMessage, LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED,
+    };
+
+    fn message(payload: Vec<u8>, flags: Flags) -> TcpRemoteNewMessage {
+        TcpRemoteNewMessage {
+            client_id: 1,
+            tag: 2,
+            flags,
+            payload,
+        }
+    }
+
+    /// Compressible, but not trivially
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|idx| u8::try_from(idx % 251).unwrap()).collect()
+    }
+
+    /// Zeros from one writer chunk after another, the bomb is never held uncompressed
+    fn bomb<W: Write>(mut encoder: W) -> W {
+        let chunk = vec![0_u8; 1 << 20];
+        for _ in 0..=MAX_DECOMPRESSED_SIZE / chunk.len() {
+            encoder.write_all(&chunk).unwrap();
+        }
+        encoder
+    }
+
+    #[test]
+    fn codecs_round_trip() {
+        let raw = payload(4096);
+        for compression in [Compression::Gzip, Compression::Lz4, Compression::Zstd] {
+            let codec = PayloadCodec::new(compression, 1024);
+            let compressed = codec.compress(&raw).unwrap().unwrap();
+            assert!(compressed.len() < raw.len(), "{compression:?}");
+            assert_eq!(codec.decompress(&compressed).unwrap(), raw, "{compression:?}");
+            // below the threshold nothing is compressed
+            assert!(codec.compress(&raw[..1023]).unwrap().is_none());
+        }
+
+        let off = PayloadCodec::new(Compression::None, 0);
+        assert!(off.compress(&raw).unwrap().is_none());
+        assert!(off.decompress(&raw).is_err());
+    }
+
+    #[test]
+    fn transcode_between_codecs() {
+        let raw = payload(4096);
+        let llmp = PayloadCodec::llmp();
+        let lz4 = PayloadCodec::new(Compression::Lz4, 64);
+        let off = PayloadCodec::new(Compression::None, 0);
+
+        let mut msg = message(llmp.compress(&raw).unwrap().unwrap(), LLMP_FLAG_COMPRESSED);
+        transcode(&llmp, &lz4, &mut msg).unwrap();
+        assert_eq!(msg.flags, LLMP_FLAG_COMPRESSED);
+        assert_eq!(lz4.decompress(&msg.payload).unwrap(), raw);
+
+        transcode(&lz4, &llmp, &mut msg).unwrap();
+        assert_eq!(llmp.decompress(&msg.payload).unwrap(), raw);
+
+        // uncompressed for the peer that wants nothing compressed
+        transcode(&llmp, &off, &mut msg).unwrap();
+        assert_eq!(msg.flags & LLMP_FLAG_COMPRESSED, 0);
+        assert_eq!(msg.payload, raw);
+        assert_eq!((msg.client_id, msg.tag), (1, 2));
+    }
+
+    #[test]
+    fn transcode_compresses_from_the_threshold() {
+        let lz4 = PayloadCodec::new(Compression::Lz4, 1024);
+        let off = PayloadCodec::new(Compression::None, 0);
+
+        let mut small = message(payload(1023), LLMP_FLAG_INITIALIZED);
+        transcode(&off, &lz4, &mut small).unwrap();
+        assert_eq!(small.payload, payload(1023));
+
+        let mut large = message(payload(1024), LLMP_FLAG_INITIALIZED);
+        transcode(&off, &lz4, &mut large).unwrap();
+        assert_eq!(large.flags, LLMP_FLAG_COMPRESSED);
+        assert_eq!(lz4.decompress(&large.payload).unwrap(), payload(1024));
+    }
+
+    #[test]
+    fn bad_payload_is_left_as_it_was() {
+        let llmp = PayloadCodec::llmp();
+        let zstd = PayloadCodec::new(Compression::Zstd, 0);
+        let mut msg = message(b"not compressed at all".to_vec(), LLMP_FLAG_COMPRESSED);
+        assert!(transcode(&llmp, &zstd, &mut msg).is_err());
+        assert_eq!(msg.payload, b"not compressed at all");
+        assert_eq!(msg.flags, LLMP_FLAG_COMPRESSED);
+    }
+
+    #[test]
+    fn lz4_size_is_checked_first() {
+        let lz4 = PayloadCodec::new(Compression::Lz4, 0);
+        let size = u32::try_from(MAX_DECOMPRESSED_SIZE + 1).unwrap();
+        let mut claim = size.to_le_bytes().to_vec();
+        claim.extend_from_slice(&[0; 16]);
+        assert!(lz4.decompress(&claim).is_err());
+        assert!(lz4.decompress(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn gzip_bomb_is_refused() {
+        let encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::best());
+        let compressed = bomb(encoder).finish().unwrap();
+        let gzip = PayloadCodec::new(Compression::Gzip, 0);
+        assert!(gzip.decompress(&compressed).is_err());
+    }
+
+    #[test]
+    fn zstd_bomb_is_refused() {
+        let encoder = zstd::stream::Encoder::new(vec![], 3).unwrap();
+        let compressed = bomb(encoder).finish().unwrap();
+        let zstd = PayloadCodec::new(Compression::Zstd, 0);
+        assert!(zstd.decompress(&compressed).is_err());
+    }
+}
@@ -0,0 +1,598 @@
+//! Length prefixed frames both sides of the nn connection speak
+//!
+//! Every frame is a big endian `u32` length followed by that many bytes of message
+//! in the [`Encoding`] chosen by the nn.
+//! The length is checked against the frame size limit before anything is allocated,
+//! so a bogus prefix cannot make the receiver reserve gigabytes.
+//!
+//! The nn side speaks through blocking [`FramedStream`], the fuzzer side serves many nn
+//! on one runtime through [`FramedLink`].
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::messages::{CodecError, Encoding};
+use super::record::{Direction, RecordWriter, SessionRecorder};
+
+/// Frame size limit used until the peers agree on theirs
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Peers may not agree on a smaller limit, handshake messages must still fit
+pub const MIN_MAX_FRAME_SIZE: u32 = 64 * 1024;
+
+/// Length prefix of every frame, a big endian `u32`
+const FRAME_PREFIX_SIZE: u64 = 4;
+
+/// Read timeouts in the middle of a frame before the peer is considered dead
+const MAX_FRAME_STALLS: usize = 10;
+
+/// Frames [`FramedLink`] reads ahead of its owner, the peer is not read further meanwhile
+const READ_AHEAD_FRAMES: usize = 16;
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// Transport failed, or the peer went away
+    Io(io::Error),
+    /// Message cannot be encoded
+    Serialize(CodecError),
+    /// Frame exceeds the agreed limit, nothing was sent or the rest was not read
+    TooLarge { size: usize, max: u32 },
+}
+
+impl FrameError {
+    /// Read timeout expired before the frame started, the stream is still usable
+    #[must_use]
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            FrameError::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "{e}"),
+            FrameError::Serialize(e) => write!(f, "cannot encode message: {e}"),
+            FrameError::TooLarge { size, max } => {
+                write!(f, "frame of {size} bytes exceeds the limit of {max} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+impl From<CodecError> for FrameError {
+    fn from(e: CodecError) -> Self {
+        FrameError::Serialize(e)
+    }
+}
+
+impl From<FrameError> for libafl::Error {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::Io(e) => e.into(),
+            FrameError::Serialize(e) => libafl::Error::serialize(e.to_string()),
+            e @ FrameError::TooLarge { .. } => libafl::Error::illegal_argument(e.to_string()),
+        }
+    }
+}
+
+/// Stream that sends and receives whole frames under a size limit
+#[derive(Debug)]
+pub struct FramedStream<S> {
+    stream: S,
+    max_frame_size: u32,
+    encoding: Encoding,
+    /// Bytes of all frames sent, length prefixes included
+    bytes_sent: u64,
+    /// Bytes of all frames received, length prefixes included
+    bytes_received: u64,
+    /// Log of the session, only the fuzzer side records
+    recorder: Option<SessionRecorder>,
+}
+
+impl<S: Read + Write> FramedStream<S> {
+    /// Frames are limited by [`DEFAULT_MAX_FRAME_SIZE`] until the handshake is done
+    #[must_use]
+    pub fn new(stream: S) -> Self {
+        Self::with_max_frame_size(stream, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    #[must_use]
+    pub fn with_max_frame_size(stream: S, max_frame_size: u32) -> Self {
+        Self {
+            stream,
+            max_frame_size,
+            encoding: Encoding::default(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            recorder: None,
+        }
+    }
+
+    /// Switch to the limit agreed on in handshake
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    #[must_use]
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Log every frame from now on, sent frames go to nn
+    pub fn record_to(&mut self, recorder: SessionRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Switch to the encoding chosen by nn
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Received frames are decoded with it
+    #[must_use]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Encode message and send it, see [`send_frame`]
+    ///
+    /// # Errors
+    ///    if message cannot be encoded, is larger than the limit (nothing is written then)
+    ///    or stream fails
+    ///
+    pub fn send<T: Serialize>(&mut self, msg: &T) -> Result<(), FrameError> {
+        let bytes = self.encoding.encode(msg)?;
+        self.send_bytes(&bytes)
+    }
+
+    /// Send already encoded message, see [`send_frame`]
+    ///
+    /// # Errors
+    ///    if message is larger than the limit (nothing is written then) or stream fails
+    ///
+    pub fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), FrameError> {
+        send_frame(&mut self.stream, bytes, self.max_frame_size)?;
+        self.bytes_sent += FRAME_PREFIX_SIZE + bytes.len() as u64;
+        self.record(Direction::ToNn, bytes);
+        Ok(())
+    }
+
+    /// See [`recv_frame`]
+    ///
+    /// # Errors
+    ///    if frame is larger than the limit, peer stalls mid-frame or stream fails
+    ///
+    pub fn recv(&mut self) -> Result<Vec<u8>, FrameError> {
+        let bytes = recv_frame(&mut self.stream, self.max_frame_size)?;
+        self.bytes_received += FRAME_PREFIX_SIZE + bytes.len() as u64;
+        self.record(Direction::FromNn, &bytes);
+        Ok(bytes)
+    }
+
+    /// Broken log is given up, the session goes on without it
+    fn record(&mut self, direction: Direction, frame: &[u8]) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(direction, frame) {
+                eprintln!("Session log {} is abandoned: {e}", recorder.path().display());
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Bytes of all frames sent so far, length prefixes included
+    #[must_use]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Bytes of all frames received so far, length prefixes included
+    #[must_use]
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+}
+
+/// The limit both peers can live with, `None` if theirs is below [`MIN_MAX_FRAME_SIZE`]
+#[must_use]
+pub fn negotiate_max_frame_size(ours: u32, theirs: u32) -> Option<u32> {
+    (theirs >= MIN_MAX_FRAME_SIZE).then(|| ours.min(theirs))
+}
+
+/// Send encoded message as one frame
+///
+/// # Errors
+///    if message is larger than `max_frame_size` (nothing is written then) or stream fails
+///
+pub fn send_frame<S: Write>(
+    stream: &mut S,
+    msg: &[u8],
+    max_frame_size: u32,
+) -> Result<(), FrameError> {
+    let len = frame_len(msg, max_frame_size)?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(msg)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Length prefix of message, it must fit the limit
+fn frame_len(msg: &[u8], max_frame_size: u32) -> Result<u32, FrameError> {
+    match u32::try_from(msg.len()) {
+        Ok(len) if len <= max_frame_size => Ok(len),
+        _ => Err(FrameError::TooLarge {
+            size: msg.len(),
+            max: max_frame_size,
+        }),
+    }
+}
+
+/// Receive one frame of `u32` len and `[u8; len]` bytes
+///
+/// Only a timeout before the first byte is reported as timeout,
+/// the stream is out of sync after a partial read or an oversize frame.
+///
+/// # Errors
+///    if frame is larger than `max_frame_size`, peer stalls mid-frame or stream fails
+///
+pub fn recv_frame<S: Read>(stream: &mut S, max_frame_size: u32) -> Result<Vec<u8>, FrameError> {
+    let mut size_bytes = [0_u8; 4];
+    let received = loop {
+        match stream.read(&mut size_bytes) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(received) => break received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    };
+    read_frame_part(stream, &mut size_bytes[received..])?;
+
+    let size = u32::from_be_bytes(size_bytes);
+    if size > max_frame_size {
+        return Err(FrameError::TooLarge {
+            size: size as usize,
+            max: max_frame_size,
+        });
+    }
+
+    let mut bytes = vec![0_u8; size as usize];
+    read_frame_part(stream, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Read the rest of a started frame, the peer is dead if it stalls for too long
+fn read_frame_part<S: Read>(stream: &mut S, mut buf: &mut [u8]) -> io::Result<()> {
+    let mut stalls = 0;
+    while !buf.is_empty() {
+        match stream.read(buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(received) => buf = &mut buf[received..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                stalls += 1;
+                if stalls > MAX_FRAME_STALLS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "peer stalled in the middle of message",
+                    ));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Async counterpart of [`FramedStream`], used by the fuzzer side.
+///
+/// Frames are read ahead by a task of their own, so waiting for one can be given up
+/// (in `tokio::select!`) without leaving the stream in the middle of a frame.
+pub struct FramedLink<S> {
+    writer: WriteHalf<S>,
+    frames: mpsc::Receiver<Result<Vec<u8>, FrameError>>,
+    reader: JoinHandle<()>,
+    /// Shared with the reader task, checked for every frame
+    max_frame_size: Arc<AtomicU32>,
+    /// A peer that does not take our frames for this long is dead
+    write_timeout: Duration,
+    encoding: Encoding,
+    /// Bytes of all frames sent, length prefixes included
+    bytes_sent: u64,
+    /// Bytes of all frames received, length prefixes included
+    bytes_received: u64,
+    /// Log of the session
+    recorder: Option<RecordWriter>,
+}
+
+impl<S> FramedLink<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    /// Start reading frames of the stream, must be called within tokio runtime
+    #[must_use]
+    pub fn new(stream: S, max_frame_size: u32, write_timeout: Duration) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        let (frames_tx, frames) = mpsc::channel(READ_AHEAD_FRAMES);
+        let max_frame_size = Arc::new(AtomicU32::new(max_frame_size));
+        let reader = tokio::spawn(read_frames(reader, max_frame_size.clone(), frames_tx));
+
+        Self {
+            writer,
+            frames,
+            reader,
+            max_frame_size,
+            write_timeout,
+            encoding: Encoding::default(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            recorder: None,
+        }
+    }
+
+    /// Switch to the limit agreed on in handshake
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size.store(max_frame_size, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size.load(Ordering::Relaxed)
+    }
+
+    /// Log every frame from now on, sent frames go to nn
+    pub fn record_to(&mut self, recorder: SessionRecorder) {
+        self.recorder = Some(RecordWriter::spawn(recorder));
+    }
+
+    /// Switch to the encoding chosen by nn
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Received frames are decoded with it
+    #[must_use]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Encode message and send it, see [`FramedLink::send_bytes`]
+    ///
+    /// # Errors
+    ///    if message cannot be encoded, is larger than the limit (nothing is written then),
+    ///    peer does not take it in time or stream fails
+    ///
+    pub async fn send<T: Serialize>(&mut self, msg: &T) -> Result<(), FrameError> {
+        let bytes = self.encoding.encode(msg)?;
+        self.send_bytes(&bytes).await
+    }
+
+    /// Send already encoded message as one frame
+    ///
+    /// # Errors
+    ///    if message is larger than the limit (nothing is written then),
+    ///    peer does not take it in time or stream fails
+    ///
+    pub async fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), FrameError> {
+        let len = frame_len(bytes, self.max_frame_size())?;
+        let writer = &mut self.writer;
+        let write = async {
+            writer.write_all(&len.to_be_bytes()).await?;
+            writer.write_all(bytes).await?;
+            writer.flush().await
+        };
+        match tokio::time::timeout(self.write_timeout, write).await {
+            Ok(res) => res?,
+            Err(_) => return Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+        }
+
+        self.bytes_sent += FRAME_PREFIX_SIZE + bytes.len() as u64;
+        self.record(Direction::ToNn, bytes);
+        Ok(())
+    }
+
+    /// Next frame of the peer. Cancel safe, a frame is never lost
+    ///
+    /// # Errors
+    ///    if frame is larger than the limit or stream fails, no frames follow then
+    ///
+    pub async fn recv(&mut self) -> Result<Vec<u8>, FrameError> {
+        let bytes = match self.frames.recv().await {
+            Some(frame) => frame?,
+            // reader is gone after the error it reported
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        };
+        self.bytes_received += FRAME_PREFIX_SIZE + bytes.len() as u64;
+        self.record(Direction::FromNn, &bytes);
+        Ok(bytes)
+    }
+
+    /// [`FramedLink::recv`] giving up after `timeout`, the link is still usable then
+    ///
+    /// # Errors
+    ///    if nothing arrives in time (see [`FrameError::is_timeout`]),
+    ///    frame is larger than the limit or stream fails
+    ///
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, FrameError> {
+        match tokio::time::timeout(timeout, self.recv()).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+        }
+    }
+
+    /// Broken log is given up, the session goes on without it
+    fn record(&mut self, direction: Direction, frame: &[u8]) {
+        if let Some(recorder) = self.recorder.as_ref() {
+            // the writer has told why
+            if recorder.record(direction, frame).is_err() {
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Bytes of all frames sent so far, length prefixes included
+    #[must_use]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Bytes of all frames received so far, length prefixes included
+    #[must_use]
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+}
+
+impl<S> Drop for FramedLink<S> {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Pass frames to the link until the stream fails or the link is dropped
+async fn read_frames<S: AsyncRead>(
+    mut reader: ReadHalf<S>,
+    max_frame_size: Arc<AtomicU32>,
+    frames: mpsc::Sender<Result<Vec<u8>, FrameError>>,
+) {
+    loop {
+        let frame = read_frame(&mut reader, &max_frame_size).await;
+        // the stream is out of sync after any error
+        let failed = frame.is_err();
+        if frames.send(frame).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Receive one frame, the limit is taken when its length is known
+async fn read_frame<S: AsyncRead>(
+    reader: &mut ReadHalf<S>,
+    max_frame_size: &AtomicU32,
+) -> Result<Vec<u8>, FrameError> {
+    let mut size_bytes = [0_u8; 4];
+    reader.read_exact(&mut size_bytes).await?;
+
+    let size = u32::from_be_bytes(size_bytes);
+    let max = max_frame_size.load(Ordering::Relaxed);
+    if size > max {
+        return Err(FrameError::TooLarge {
+            size: size as usize,
+            max,
+        });
+    }
+
+    let mut bytes = vec![0_u8; size as usize];
+    reader.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use super::{
+        negotiate_max_frame_size, recv_frame, send_frame, FrameError, FramedLink,
+        MIN_MAX_FRAME_SIZE,
+    };
+
+    #[test]
+    fn smaller_limit_wins() {
+        assert_eq!(negotiate_max_frame_size(1 << 20, 1 << 24), Some(1 << 20));
+        assert_eq!(negotiate_max_frame_size(1 << 24, 1 << 20), Some(1 << 20));
+        assert_eq!(
+            negotiate_max_frame_size(1 << 20, MIN_MAX_FRAME_SIZE),
+            Some(MIN_MAX_FRAME_SIZE)
+        );
+    }
+
+    #[test]
+    fn too_small_limit_is_refused() {
+        // handshake must still fit, the limit is never raised behind the peer's back
+        assert_eq!(negotiate_max_frame_size(1 << 20, MIN_MAX_FRAME_SIZE - 1), None);
+        assert_eq!(negotiate_max_frame_size(1 << 20, 16), None);
+        assert_eq!(negotiate_max_frame_size(1 << 20, 0), None);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut wire = vec![];
+        send_frame(&mut wire, b"hello", 16).unwrap();
+        send_frame(&mut wire, b"", 16).unwrap();
+        assert_eq!(&wire[..4], &5_u32.to_be_bytes());
+
+        let mut wire = Cursor::new(wire);
+        assert_eq!(recv_frame(&mut wire, 16).unwrap(), b"hello");
+        assert_eq!(recv_frame(&mut wire, 16).unwrap(), b"");
+        assert!(recv_frame(&mut wire, 16).is_err());
+    }
+
+    #[test]
+    fn oversize_frame_is_not_sent() {
+        let mut wire = vec![];
+        let res = send_frame(&mut wire, &[0_u8; 17], 16);
+        assert!(matches!(res, Err(FrameError::TooLarge { size: 17, max: 16 })));
+        assert!(wire.is_empty());
+    }
+
+    #[test]
+    fn oversize_frame_is_not_read() {
+        // the prefix alone is enough to refuse it
+        let mut wire = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let res = recv_frame(&mut wire, 16);
+        assert!(matches!(res, Err(FrameError::TooLarge { max: 16, .. })));
+    }
+
+    #[tokio::test]
+    async fn link_enforces_the_limit() {
+        let (ours, theirs) = tokio::io::duplex(1024);
+        let mut ours = FramedLink::new(ours, 16, Duration::from_secs(1));
+        let mut theirs = FramedLink::new(theirs, 64, Duration::from_secs(1));
+
+        theirs.send_bytes(&[1; 16]).await.unwrap();
+        assert_eq!(ours.recv().await.unwrap(), [1; 16]);
+        assert!(matches!(
+            ours.send_bytes(&[1; 17]).await,
+            Err(FrameError::TooLarge { size: 17, max: 16 })
+        ));
+
+        theirs.send_bytes(&[2; 17]).await.unwrap();
+        assert!(matches!(
+            ours.recv().await,
+            Err(FrameError::TooLarge { size: 17, max: 16 })
+        ));
+        // nothing follows a frame out of sync
+        assert!(ours.recv().await.is_err());
+    }
+}
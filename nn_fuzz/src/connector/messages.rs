@@ -1,15 +1,230 @@
-use serde::{Deserialize, Serialize};
+use core::fmt;
+use core::time::Duration;
+
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use postcard::Error as Error;
 
-use libafl::prelude::{ClientId, Flags, Tag};
+use libafl::prelude::{ClientId, Event, ExitKind, Flags, Input, Tag};
+
+/// The tag of fuzzer events in llmp
+pub const LLMP_TAG_EVENT_TO_BOTH: Tag = 0x002B_0741;
+/// Sent to the broker by nn service, the broker publishes its stats then
+pub const LLMP_TAG_NN_STATS_TICK: Tag = 0x002B_0742;
 
 pub const LLMP_FLAG_INITIALIZED: Flags = 0x0;
 pub const LLMP_FLAG_FROM_NN: Flags = 0x4;
 pub const LLMP_FLAG_COMPRESSED: Flags = 0x1;
+/// Set by broker on replies addressed to one nn, [`NnReward`] or [`NnControlAck`]
+pub const LLMP_FLAG_NN_REPLY: Flags = 0x8;
+
+/// Tag of custom buffer holding [`NnInputRequest`]
+pub const NN_INPUT_TAG: &str = "nn_input";
+/// Tag of custom buffer holding [`NnInputBatch`]
+pub const NN_INPUT_BATCH_TAG: &str = "nn_input_batch";
+/// Tag of custom buffer holding [`NnReward`]
+pub const NN_REWARD_TAG: &str = "nn_reward";
+/// Tag of custom buffer holding [`NnControlRequest`]
+pub const NN_CONTROL_TAG: &str = "nn_control";
+/// Tag of custom buffer holding [`NnControlAck`]
+pub const NN_CONTROL_ACK_TAG: &str = "nn_control_ack";
+
+/// Largest mutation stack power nn may set, `2^16` stacked mutations
+pub const MAX_STACK_POW: u64 = 16;
+
+/// Largest iteration budget nn may set, a testcase must not hold a fuzzer for hours
+pub const MAX_ITERATIONS: u64 = 1 << 16;
 
 /// The minimum buffer size at which to compress LLMP IPC messages.
+/// Nn connections agree on their own, see [`Compression`]
 pub const COMPRESS_THRESHOLD: usize = 1024;
 
+/// Version of the nn protocol, bump on every incompatible wire change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features of a protocol peer, announced in both hellos.
+pub type Capabilities = u64;
+
+/// Testcases carry serialized observers
+pub const CAP_OBSERVERS: Capabilities = 0x1;
+
+/// New testcases are sent as [`NnTestcase`] instead of raw llmp events, opt-in for nn
+pub const CAP_STRUCTURED: Capabilities = 0x2;
+
+/// Read the protocol version of a hello message without decoding the rest of it.
+///
+/// Every hello keeps `protocol_version` as its first field,
+/// so this works for peers speaking any version of the protocol.
+#[must_use]
+pub fn peek_protocol_version(bytes: &[u8]) -> Option<u32> {
+    // enum variant index goes first, then the version
+    postcard::take_from_bytes::<(u32, u32)>(bytes)
+        .ok()
+        .map(|((_, version), _)| version)
+}
+
+/// How messages are serialized inside frames.
+///
+/// The fuzzer opens the connection with its hello in postcard. A nn speaking another
+/// encoding answers with a frame holding the [`Encoding::name`], the fuzzer repeats
+/// its hello in it and every message after it uses that encoding.
+/// A postcard nn sends its request right away.
+/// Text based encodings let nn written in other languages talk to fuzzer,
+/// they should ask for [`CAP_STRUCTURED`] as llmp events stay postcard encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Postcard,
+    /// Enums are objects keyed by the variant name
+    Json,
+    /// Structs are maps keyed by the field name, enums as in JSON
+    MessagePack,
+}
+
+/// Codec of payloads flagged with [`LLMP_FLAG_COMPRESSED`] on one nn connection.
+///
+/// Llmp always uses gzip above [`COMPRESS_THRESHOLD`], the connector transcodes
+/// messages to the codec and threshold agreed on in handshake and back.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Payloads are never compressed, cheapest for nn on the same host
+    None,
+    #[default]
+    Gzip,
+    /// Fast, weaker compression
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// Every codec in order of preference, nn offers this unless told otherwise
+    pub const ALL: [Compression; 4] = [
+        Compression::Zstd,
+        Compression::Lz4,
+        Compression::Gzip,
+        Compression::None,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "gzip" => Some(Compression::Gzip),
+            "lz4" => Some(Compression::Lz4),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The first codec of the peer, this build handles all of them.
+    /// Uncompressed if the peer offers nothing
+    #[must_use]
+    pub fn negotiate(offered: &[Compression]) -> Self {
+        offered.first().copied().unwrap_or(Compression::None)
+    }
+}
+
+/// Message cannot be encoded or decoded
+#[derive(Debug)]
+pub struct CodecError(pub String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<CodecError> for libafl::Error {
+    fn from(e: CodecError) -> Self {
+        libafl::Error::serialize(e.0)
+    }
+}
+
+impl Encoding {
+    /// Name the nn sends to choose the encoding
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Postcard => "postcard",
+            Encoding::Json => "json",
+            Encoding::MessagePack => "msgpack",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"postcard" => Some(Encoding::Postcard),
+            b"json" => Some(Encoding::Json),
+            b"msgpack" => Some(Encoding::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// # Errors
+    ///    if message cannot be serialized
+    ///
+    pub fn encode<T: Serialize>(self, msg: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Encoding::Postcard => {
+                postcard::to_allocvec(msg).map_err(|e| CodecError(e.to_string()))
+            }
+            Encoding::Json => serde_json::to_vec(msg).map_err(|e| CodecError(e.to_string())),
+            Encoding::MessagePack => {
+                rmp_serde::to_vec_named(msg).map_err(|e| CodecError(e.to_string()))
+            }
+        }
+    }
+
+    /// # Errors
+    ///    if bytes do not hold the message
+    ///
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Encoding::Postcard => {
+                postcard::from_bytes(bytes).map_err(|e| CodecError(e.to_string()))
+            }
+            Encoding::Json => serde_json::from_slice(bytes).map_err(|e| CodecError(e.to_string())),
+            Encoding::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|e| CodecError(e.to_string()))
+            }
+        }
+    }
+
+    /// [`peek_protocol_version`] for any encoding
+    #[must_use]
+    pub fn peek_protocol_version(self, bytes: &[u8]) -> Option<u32> {
+        /// Any hello, other fields are ignored
+        #[derive(Deserialize)]
+        struct Versioned {
+            protocol_version: u32,
+        }
+
+        match self {
+            Encoding::Postcard => peek_protocol_version(bytes),
+            // self describing, the version is found by name
+            Encoding::Json | Encoding::MessagePack => self
+                .decode::<HashMap<String, Versioned>>(bytes)
+                .ok()
+                .and_then(|hello| hello.into_values().next())
+                .map(|hello| hello.protocol_version),
+        }
+    }
+}
+
 /// Messages for nn connection.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TcpRemoteNewMessage {
@@ -44,17 +259,46 @@ impl TryFrom<Vec<u8>> for TcpRemoteNewMessage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TcpResponce {
     /// After receiving new connection, the broker send hello
-    RemoteFuzzerHello { fuzz_description: FuzzerDescription },
+    RemoteFuzzerHello {
+        /// Must stay the first field, see [`peek_protocol_version`]
+        protocol_version: u32,
+        /// Features supported by the fuzzer
+        capabilities: Capabilities,
+        /// Largest frame the fuzzer accepts, see [`super::framing`]
+        max_frame_size: u32,
+        /// Challenge to sign with the shared secret, `None` if fuzzer does not require it
+        auth_nonce: Option<Vec<u8>>,
+        fuzz_description: FuzzerDescription,
+    },
     // Notify the client nn that it has been accepted
     RemoteNNAccepted {
         /// The clientId this client should send messages as.
         client_id: ClientId,
+        /// Features supported by both sides
+        capabilities: Capabilities,
+        /// Frame size limit both sides enforce from now on
+        max_frame_size: u32,
+        /// Codec of compressed payloads both sides use from now on
+        compression: Compression,
+        /// Payloads from this size on are compressed
+        compress_threshold: u32,
     },
     /// Something went wrong when processing the request
     Error {
         /// Error description
         description: String,
     },
+    /// Notify the local fuzzer that it has been accepted
+    LocalAccepted {
+        /// The clientId the broker gave the connection, messages of this fuzzer carry it in llmp
+        client_id: ClientId,
+        /// Frame size limit both sides enforce from now on
+        max_frame_size: u32,
+        /// Codec of compressed payloads both sides use from now on
+        compression: Compression,
+        /// Payloads from this size on are compressed
+        compress_threshold: u32,
+    },
 }
 
 impl TryFrom<&Vec<u8>> for TcpResponce {
@@ -78,17 +322,84 @@ impl TryFrom<Vec<u8>> for TcpResponce {
 pub enum TcpRequest {
     /// After sending hello wait for hello from nn
     RemoteNnHello {
+        /// Must stay the first field, see [`peek_protocol_version`]
+        protocol_version: u32,
+        /// Features supported by the nn
+        capabilities: Capabilities,
+        /// Largest frame the nn accepts, at least [`super::framing::MIN_MAX_FRAME_SIZE`]
+        max_frame_size: u32,
         /// Additional info about nn env and settings
         nn_name: String,
         nn_version: String,
+        /// Events the nn wants to receive
+        subscription: EventSubscription,
+        /// Send testcases stored on disk before live messages, see [`StoredTestcase`]
+        replay: bool,
+        /// Codecs the nn handles, most wanted first, see [`Compression::negotiate`]
+        compression: Vec<Compression>,
+        /// Payloads from this size on are compressed
+        compress_threshold: u32,
+        /// Signed `auth_nonce` of the fuzzer hello, see [`super::auth::sign`]
+        auth: Option<Vec<u8>>,
     },
     /// After sending hello wait for hello from local fuzzer instances
     LocalHello {
-        /// Additional info about local fuzzer
-        client_id: ClientId,
+        /// Must stay the first field, see [`peek_protocol_version`]
+        protocol_version: u32,
+        /// Largest frame the local fuzzer accepts, at least [`super::framing::MIN_MAX_FRAME_SIZE`]
+        max_frame_size: u32,
+        /// Codecs the local fuzzer handles, most wanted first
+        compression: Vec<Compression>,
+        /// Payloads from this size on are compressed
+        compress_threshold: u32,
+        /// Signed `auth_nonce` of the fuzzer hello, see [`super::auth::sign`]
+        auth: Option<Vec<u8>>,
     },
 }
 
+impl TcpRequest {
+    /// Answer to the challenge of fuzzer hello
+    #[must_use]
+    pub fn auth(&self) -> Option<&[u8]> {
+        match self {
+            TcpRequest::RemoteNnHello { auth, .. } | TcpRequest::LocalHello { auth, .. } => {
+                auth.as_deref()
+            }
+        }
+    }
+
+    /// Largest frame the peer accepts
+    #[must_use]
+    pub fn max_frame_size(&self) -> u32 {
+        match self {
+            TcpRequest::RemoteNnHello { max_frame_size, .. }
+            | TcpRequest::LocalHello { max_frame_size, .. } => *max_frame_size,
+        }
+    }
+
+    /// Codecs the peer handles, most wanted first
+    #[must_use]
+    pub fn compression(&self) -> &[Compression] {
+        match self {
+            TcpRequest::RemoteNnHello { compression, .. }
+            | TcpRequest::LocalHello { compression, .. } => compression,
+        }
+    }
+
+    /// Payloads from this size on are compressed
+    #[must_use]
+    pub fn compress_threshold(&self) -> u32 {
+        match self {
+            TcpRequest::RemoteNnHello {
+                compress_threshold, ..
+            }
+            | TcpRequest::LocalHello {
+                compress_threshold, ..
+            } => *compress_threshold,
+        }
+    }
+}
+
 impl TryFrom<Vec<u8>> for TcpRequest {
     type Error = Error;
 
@@ -105,6 +416,493 @@ impl TryFrom<&Vec<u8>> for TcpRequest {
     }
 }
 
+/// Messages from nn after handshake
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TcpNnMessage {
+    /// Message to broadcast over fuzzers
+    NewMessage(TcpRemoteNewMessage),
+    /// Inputs every fuzzer instance evaluates, as [`NnInputBatch`] the fuzzer builds itself.
+    /// Nn in any encoding may submit them, llmp events are postcard only
+    SubmitInputs {
+        /// Request id of the first input, the next ones follow it
+        first_request_id: u64,
+        inputs: Vec<Vec<u8>>,
+    },
+    /// Replace the current event subscription
+    Subscribe(EventSubscription),
+    /// Ask for all connected nn
+    ListNns,
+    /// Disconnect other connection of the same nn, a stale one after reconnect.
+    /// Allowed to nn authenticated with the shared secret only
+    KickNn {
+        /// The clientId of nn to disconnect
+        client_id: ClientId,
+    },
+    /// Heartbeat, fuzzer answers with [`TcpFuzzerMessage::Pong`]
+    Ping { seq: u64 },
+    /// Answer to [`TcpFuzzerMessage::Ping`]
+    Pong { seq: u64 },
+    /// Ask for the numbers of broker monitor
+    GetStats,
+    /// Ask for corpus or solutions entry by its file name, see [`StoredTestcase::name`]
+    GetCorpusEntry { kind: StoredKind, name: String },
+    /// Change fuzzer settings, every affected fuzzer answers with [`TcpFuzzerMessage::ControlAck`]
+    Control {
+        /// Chosen by nn to match acks with commands
+        command_id: u64,
+        /// Fuzzer clients to apply the command, all of them if `None`
+        targets: Option<Vec<ClientId>>,
+        command: ControlCommand,
+    },
+}
+
+impl TryFrom<Vec<u8>> for TcpNnMessage {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Error> {
+        postcard::from_bytes(bytes.as_slice())
+    }
+}
+
+impl TryFrom<&Vec<u8>> for TcpNnMessage {
+    type Error = Error;
+
+    fn try_from(bytes: &Vec<u8>) -> Result<Self, Error> {
+        postcard::from_bytes(bytes.as_slice())
+    }
+}
+
+/// Messages to nn after handshake
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TcpFuzzerMessage {
+    /// Message broadcasted by fuzzers
+    NewMessage(TcpRemoteNewMessage),
+    /// All connected nn, reply to [`TcpNnMessage::ListNns`]
+    NnList(Vec<NnInfo>),
+    /// Reply to [`TcpNnMessage::KickNn`]
+    Kicked {
+        client_id: ClientId,
+        /// `false` if there was no such nn
+        found: bool,
+    },
+    /// Something went wrong, connection may be closed after that
+    Error {
+        /// Error description
+        description: String,
+    },
+    /// Heartbeat, nn must answer with [`TcpNnMessage::Pong`] before the liveness timeout
+    Ping { seq: u64 },
+    /// Answer to [`TcpNnMessage::Ping`]
+    Pong { seq: u64 },
+    /// New testcase decoded by fuzzer, sent instead of [`Self::NewMessage`] if
+    /// [`CAP_STRUCTURED`] is agreed on
+    Testcase(NnTestcase),
+    /// Testcase found before nn connected, if nn asked for replay
+    Stored(StoredTestcase),
+    /// All stored testcases are sent, live messages follow
+    ReplayDone { corpus: u64, solutions: u64 },
+    /// Reply to [`TcpNnMessage::GetStats`]
+    Stats(FuzzerStats),
+    /// Reply to [`TcpNnMessage::GetCorpusEntry`]
+    CorpusEntry {
+        kind: StoredKind,
+        name: String,
+        /// `None` if there is no such entry
+        testcase: Option<StoredTestcase>,
+    },
+    /// How a fuzzer instance rated an input of this nn, one per instance
+    Reward {
+        /// The fuzzer which evaluated the input
+        fuzzer_id: ClientId,
+        reward: NnReward,
+    },
+    /// Result of [`TcpNnMessage::Control`], one per affected fuzzer
+    ControlAck {
+        command_id: u64,
+        /// The fuzzer which applied the command, `None` if the command was not sent to fuzzers
+        fuzzer_id: Option<ClientId>,
+        /// Why the command was not applied, `None` if it was
+        error: Option<String>,
+    },
+}
+
+impl TryFrom<Vec<u8>> for TcpFuzzerMessage {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Error> {
+        postcard::from_bytes(bytes.as_slice())
+    }
+}
+
+impl TryFrom<&Vec<u8>> for TcpFuzzerMessage {
+    type Error = Error;
+
+    fn try_from(bytes: &Vec<u8>) -> Result<Self, Error> {
+        postcard::from_bytes(bytes.as_slice())
+    }
+}
+
+/// New testcase in a form that does not depend on libafl serialization
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnTestcase {
+    /// The client which found the testcase
+    pub client_id: ClientId,
+    pub input: Vec<u8>,
+    pub exit_kind: NnExitKind,
+    /// Run time of the input, `None` if client did not send observers
+    pub exec_time: Option<Duration>,
+    /// Executions of the client so far
+    pub executions: u64,
+    /// Corpus size of the client after adding the testcase
+    pub corpus_size: u64,
+    /// Time the testcase was found since unix epoch
+    pub time: Duration,
+    /// Nonzero edges as `(edge index, hit count)`, `None` if client did not send observers
+    /// or nn is not subscribed to them
+    pub coverage: Option<Vec<(u32, u8)>>,
+}
+
+/// Where the stored testcase comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredKind {
+    Corpus,
+    Solution,
+}
+
+impl StoredKind {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            StoredKind::Corpus => "corpus",
+            StoredKind::Solution => "solution",
+        }
+    }
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "corpus" => Some(StoredKind::Corpus),
+            "solution" => Some(StoredKind::Solution),
+            _ => None,
+        }
+    }
+}
+
+/// Testcase read from the corpus or solutions directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredTestcase {
+    pub kind: StoredKind,
+    /// File name of the testcase, it stays the same while the file exists,
+    /// see [`TcpNnMessage::GetCorpusEntry`]
+    pub name: String,
+    pub input: Vec<u8>,
+    /// Run time of the input, if stored in metadata
+    pub exec_time: Option<Duration>,
+    /// Executions of the client when the testcase was found, if stored in metadata
+    pub executions: Option<u64>,
+    /// Raw JSON of the `.<name>.metadata` file
+    pub metadata: Option<String>,
+}
+
+/// Input submitted by nn, every fuzzer instance evaluates it and answers with [`NnReward`].
+/// Sent as custom buffer tagged [`NN_INPUT_TAG`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnInputRequest {
+    /// Chosen by nn to match rewards with inputs
+    pub request_id: u64,
+    /// The clientId of nn the reward goes to
+    pub client_id: ClientId,
+    pub input: Vec<u8>,
+}
+
+/// Inputs submitted by nn at once, the event is compressed as a whole.
+/// Sent as custom buffer tagged [`NN_INPUT_BATCH_TAG`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnInputBatch {
+    /// Request id of the first input, the next ones follow it
+    pub first_request_id: u64,
+    /// The clientId of nn the rewards go to
+    pub client_id: ClientId,
+    pub inputs: Vec<Vec<u8>>,
+}
+
+impl NnInputBatch {
+    /// Unpack into requests evaluated one by one
+    pub fn into_requests(self) -> impl Iterator<Item = NnInputRequest> {
+        let (first_request_id, client_id) = (self.first_request_id, self.client_id);
+        (first_request_id..)
+            .zip(self.inputs)
+            .map(move |(request_id, input)| NnInputRequest {
+                request_id,
+                client_id,
+                input,
+            })
+    }
+}
+
+/// Runtime change of fuzzer settings
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Stop fuzzing, events are still processed
+    Pause,
+    /// Go on after [`Self::Pause`]
+    Resume,
+    /// Mutational stage runs `1..=max` iterations per testcase
+    SetIterations { max: u64 },
+    /// Mutations are stacked `2^(1..=pow)` times
+    SetStackPow { pow: u64 },
+}
+
+impl ControlCommand {
+    /// Check the values before any fuzzer sees the command
+    ///
+    /// # Errors
+    ///    if the values are out of range
+    ///
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ControlCommand::SetIterations { max } if max == 0 || max > MAX_ITERATIONS => Err(
+                format!("iteration budget must be in 1..={MAX_ITERATIONS}, got {max}"),
+            ),
+            ControlCommand::SetStackPow { pow } if pow == 0 || pow > MAX_STACK_POW => Err(format!(
+                "stack power must be in 1..={MAX_STACK_POW}, got {pow}"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// [`TcpNnMessage::Control`] as fuzzers get it, sent as custom buffer tagged [`NN_CONTROL_TAG`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnControlRequest {
+    pub command_id: u64,
+    /// The clientId of nn the acks go to
+    pub client_id: ClientId,
+    /// Fuzzer clients to apply the command, all of them if `None`
+    pub targets: Option<Vec<ClientId>>,
+    pub command: ControlCommand,
+}
+
+/// Answer to [`NnControlRequest`], sent as custom buffer tagged [`NN_CONTROL_ACK_TAG`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnControlAck {
+    pub command_id: u64,
+    /// The clientId of nn which sent the command
+    pub client_id: ClientId,
+    /// Why the command was not applied, `None` if it was
+    pub error: Option<String>,
+}
+
+/// What the input added to the fuzzer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Novelty {
+    /// Nothing new
+    None,
+    /// Added to the corpus
+    Corpus,
+    /// Added to the solutions
+    Solution,
+}
+
+impl Novelty {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Novelty::None => "none",
+            Novelty::Corpus => "corpus",
+            Novelty::Solution => "solution",
+        }
+    }
+}
+
+/// Result of [`NnInputRequest`] evaluation, sent as custom buffer tagged [`NN_REWARD_TAG`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnReward {
+    pub request_id: u64,
+    /// The clientId of nn which submitted the input
+    pub client_id: ClientId,
+    pub exit_kind: NnExitKind,
+    pub novelty: Novelty,
+    /// Edges never hit by this fuzzer instance before
+    pub new_edges: u64,
+    pub exec_time: Option<Duration>,
+}
+
+/// How the execution of testcase ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NnExitKind {
+    Ok,
+    Crash,
+    Oom,
+    Timeout,
+    /// Anything else libafl reports
+    Other,
+}
+
+impl From<&ExitKind> for NnExitKind {
+    fn from(kind: &ExitKind) -> Self {
+        match kind {
+            ExitKind::Ok => NnExitKind::Ok,
+            ExitKind::Crash => NnExitKind::Crash,
+            ExitKind::Oom => NnExitKind::Oom,
+            ExitKind::Timeout => NnExitKind::Timeout,
+            ExitKind::Diff { .. } => NnExitKind::Other,
+        }
+    }
+}
+
+impl NnExitKind {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            NnExitKind::Ok => "ok",
+            NnExitKind::Crash => "crash",
+            NnExitKind::Oom => "oom",
+            NnExitKind::Timeout => "timeout",
+            NnExitKind::Other => "other",
+        }
+    }
+}
+
+/// Campaign numbers as the broker monitor sees them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FuzzerStats {
+    /// Time since the broker started
+    pub run_time: Duration,
+    /// Executions of all clients
+    pub executions: u64,
+    pub execs_per_sec: f64,
+    pub corpus_size: u64,
+    pub objective_size: u64,
+    /// Every llmp client, nn connections included
+    pub clients: Vec<FuzzerClientStats>,
+}
+
+/// Numbers of one llmp client
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FuzzerClientStats {
+    pub client_id: ClientId,
+    pub executions: u64,
+    pub execs_per_sec: f64,
+    pub corpus_size: u64,
+    pub objective_size: u64,
+}
+
+/// What is on the other side of a connection
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerKind {
+    /// Remote neural network
+    Nn,
+    /// Out-of-llmp tool on the same host acting as a fuzzer
+    Local,
+}
+
+impl PeerKind {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            PeerKind::Nn => "nn",
+            PeerKind::Local => "local",
+        }
+    }
+}
+
+/// Connected nn (or local fuzzer) as fuzzer sees it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NnInfo {
+    /// The clientId nn sends messages as
+    pub client_id: ClientId,
+    pub kind: PeerKind,
+    pub nn_name: String,
+    pub nn_version: String,
+    /// Time of connection since unix epoch
+    pub connected_at: Duration,
+    /// Messages sent to nn
+    pub msgs_to_nn: u64,
+    /// Messages received from nn
+    pub msgs_from_nn: u64,
+}
+
+/// Set of fuzzer event kinds
+pub type EventKinds = u32;
+
+pub const EVENT_NEW_TESTCASE: EventKinds = 0x1;
+pub const EVENT_EXEC_STATS: EventKinds = 0x2;
+pub const EVENT_USER_STATS: EventKinds = 0x4;
+pub const EVENT_OBJECTIVE: EventKinds = 0x8;
+pub const EVENT_LOG: EventKinds = 0x10;
+pub const EVENT_CUSTOM: EventKinds = 0x20;
+pub const EVENT_ALL: EventKinds = 0x3F;
+
+/// Which fuzzer events the nn wants to receive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventSubscription {
+    /// Event kinds to forward
+    pub kinds: EventKinds,
+    /// Forward events from these clients only, from all clients if `None`
+    pub clients: Option<Vec<ClientId>>,
+    /// Keep serialized observers of new testcases
+    pub observers: bool,
+}
+
+impl Default for EventSubscription {
+    /// Testcases and custom buffers of all clients, the same events llmp clients get
+    fn default() -> Self {
+        Self {
+            kinds: EVENT_NEW_TESTCASE | EVENT_CUSTOM,
+            clients: None,
+            observers: true,
+        }
+    }
+}
+
+impl EventSubscription {
+    /// Parse event kind from its name
+    #[must_use]
+    pub fn kind_from_name(name: &str) -> Option<EventKinds> {
+        match name {
+            "new_testcase" => Some(EVENT_NEW_TESTCASE),
+            "exec_stats" => Some(EVENT_EXEC_STATS),
+            "user_stats" => Some(EVENT_USER_STATS),
+            "objective" => Some(EVENT_OBJECTIVE),
+            "log" => Some(EVENT_LOG),
+            "custom" => Some(EVENT_CUSTOM),
+            _ => None,
+        }
+    }
+
+    /// Kind of the event
+    #[must_use]
+    pub fn kind_of<I: Input>(event: &Event<I>) -> EventKinds {
+        match event {
+            Event::NewTestcase { .. } => EVENT_NEW_TESTCASE,
+            Event::UpdateExecStats { .. } => EVENT_EXEC_STATS,
+            Event::UpdateUserStats { .. } => EVENT_USER_STATS,
+            Event::Objective { .. } => EVENT_OBJECTIVE,
+            Event::Log { .. } => EVENT_LOG,
+            Event::CustomBuf { .. } => EVENT_CUSTOM,
+        }
+    }
+
+    #[must_use]
+    pub fn accepts_client(&self, client_id: ClientId) -> bool {
+        self.clients
+            .as_ref()
+            .map_or(true, |clients| clients.contains(&client_id))
+    }
+
+    #[must_use]
+    pub fn accepts<I: Input>(&self, event: &Event<I>) -> bool {
+        self.kinds & Self::kind_of(event) != 0
+    }
+
+    /// Every event passes unchanged, no need to look inside messages
+    #[must_use]
+    pub fn is_everything(&self) -> bool {
+        self.kinds & EVENT_ALL == EVENT_ALL && self.clients.is_none() && self.observers
+    }
+}
+
 /// Info required by neural network to work with
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FuzzerDescription {
@@ -114,4 +912,51 @@ pub struct FuzzerDescription {
     pub instances: usize,
     /// Fuzzing target
     pub fuzz_target: String,
+    /// The arguments passed to target
+    pub fuzz_args: Vec<String>,
+    /// The timeout for each input execution (millis)
+    pub timeout: u64,
+    /// The maximum length of generated inputs
+    pub input_max_length: usize,
+    /// Tokens loaded from token files
+    pub tokens: Vec<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{peek_protocol_version, Encoding, TcpRequest};
+
+    fn local_hello(protocol_version: u32) -> TcpRequest {
+        TcpRequest::LocalHello {
+            protocol_version,
+            max_frame_size: 1024,
+            compression: vec![],
+            compress_threshold: 0,
+            auth: None,
+        }
+    }
+
+    #[test]
+    fn peek_version_of_any_encoding() {
+        for encoding in [Encoding::Postcard, Encoding::Json, Encoding::MessagePack] {
+            let bytes = encoding.encode(&local_hello(7)).unwrap();
+            assert_eq!(encoding.peek_protocol_version(&bytes), Some(7), "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn peek_version_ignores_the_rest() {
+        let bytes = Encoding::Postcard.encode(&local_hello(300)).unwrap();
+        // the version is all that must be there
+        assert_eq!(peek_protocol_version(&bytes[..3]), Some(300));
+        assert_eq!(peek_protocol_version(&bytes[..1]), None);
+        assert_eq!(peek_protocol_version(&[]), None);
+    }
+
+    #[test]
+    fn peek_version_of_garbage() {
+        assert_eq!(Encoding::Json.peek_protocol_version(b"json"), None);
+        assert_eq!(Encoding::Json.peek_protocol_version(b"{\"LocalHello\":{}}"), None);
+        assert_eq!(Encoding::MessagePack.peek_protocol_version(&[0xc1]), None);
+    }
 }
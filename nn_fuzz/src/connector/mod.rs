@@ -1,2 +1,11 @@
 pub(crate) mod server;
-pub mod messages;
\ No newline at end of file
+pub(crate) mod registry;
+pub(crate) mod backlog;
+pub(crate) mod queries;
+pub mod queue;
+pub mod messages;
+pub mod auth;
+pub mod tls;
+pub mod framing;
+pub mod record;
+pub mod compression;
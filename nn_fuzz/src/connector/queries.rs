@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use libafl::bolts::current_time;
+use libafl::monitors::Monitor;
+
+use super::backlog::find_stored;
+use super::messages::{FuzzerClientStats, FuzzerStats, StoredKind, StoredTestcase};
+
+/// Latest numbers of the broker monitor, published by broker, read by nn connections
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StatsBoard(Arc<RwLock<FuzzerStats>>);
+
+impl StatsBoard {
+    /// Take the numbers after monitor is updated
+    pub(crate) fn publish<MT: Monitor>(&self, monitor: &mut MT) {
+        let now = current_time();
+        let run_time = now.checked_sub(monitor.start_time()).unwrap_or_default();
+
+        let clients: Vec<_> = monitor
+            .client_stats_mut()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(idx, client)| {
+                let client_id = u32::try_from(idx).ok()?;
+                Some(FuzzerClientStats {
+                    client_id,
+                    executions: client.executions,
+                    execs_per_sec: client.execs_per_sec(now),
+                    corpus_size: client.corpus_size,
+                    objective_size: client.objective_size,
+                })
+            })
+            .collect();
+
+        let stats = FuzzerStats {
+            run_time,
+            executions: clients.iter().map(|client| client.executions).sum(),
+            execs_per_sec: clients.iter().map(|client| client.execs_per_sec).sum(),
+            corpus_size: clients.iter().map(|client| client.corpus_size).sum(),
+            objective_size: clients.iter().map(|client| client.objective_size).sum(),
+            clients,
+        };
+
+        // readers only copy it out, a panic there leaves it consistent
+        match self.0.write() {
+            Ok(mut current) => *current = stats,
+            Err(poisoned) => *poisoned.into_inner() = stats,
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> FuzzerStats {
+        match self.0.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+/// Answers nn queries about the campaign
+#[derive(Debug, Clone)]
+pub(crate) struct Queries {
+    stats: StatsBoard,
+    corpus_dir: Option<PathBuf>,
+    solutions_dir: Option<PathBuf>,
+}
+
+impl Queries {
+    pub(crate) fn new(
+        stats: StatsBoard,
+        corpus_dir: Option<PathBuf>,
+        solutions_dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            stats,
+            corpus_dir,
+            solutions_dir,
+        }
+    }
+
+    pub(crate) fn stats(&self) -> FuzzerStats {
+        self.stats.snapshot()
+    }
+
+    /// Corpus or solutions entry by its file name, as in [`StoredTestcase::name`]
+    pub(crate) fn corpus_entry(&self, kind: StoredKind, name: &str) -> Option<StoredTestcase> {
+        let dir = match kind {
+            StoredKind::Corpus => self.corpus_dir.as_deref()?,
+            StoredKind::Solution => self.solutions_dir.as_deref()?,
+        };
+        find_stored(kind, dir, name)
+    }
+}
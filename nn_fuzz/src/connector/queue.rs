@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use libafl::bolts::current_nanos;
+use libafl::bolts::rands::{Rand, StdRand};
+
+use super::messages::TcpFuzzerMessage;
+
+/// What to do with messages for nn when its queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OverflowPolicy {
+    /// Leave messages in llmp until nn catches up, nothing from fuzzers is lost.
+    /// Events the broker handles itself cannot wait, they are dropped
+    Block,
+    /// Drop the oldest queued message to make room for the new one
+    DropOldest,
+    /// Keep a uniform sample of all messages arrived since the queue was empty
+    Sample,
+}
+
+/// Bounded queue of messages waiting to be sent to nn.
+/// Replies to requests of nn are kept apart, the policy never applies to them
+#[derive(Debug)]
+pub(crate) struct OutQueue {
+    /// Messages with the time they were queued
+    items: VecDeque<(Instant, TcpFuzzerMessage)>,
+    /// Replies with the time they were queued, they go before other messages
+    replies: VecDeque<(Instant, TcpFuzzerMessage)>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Messages pushed since the queue was empty, the sample is taken from them
+    offered: u64,
+    /// Messages lost by overflow
+    dropped: u64,
+    /// The longest queue since the last report
+    peak: usize,
+    /// Time popped messages spent in the queue since the last report
+    waited: Duration,
+    /// Messages popped since the last report
+    popped: u32,
+    rand: StdRand,
+}
+
+impl OutQueue {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            replies: VecDeque::new(),
+            capacity: capacity.max(1),
+            policy,
+            offered: 0,
+            dropped: 0,
+            peak: 0,
+            waited: Duration::ZERO,
+            popped: 0,
+            rand: StdRand::with_seed(current_nanos()),
+        }
+    }
+
+    /// New message can be pushed, `false` only for blocking queue
+    pub(crate) fn accepts_more(&self) -> bool {
+        self.policy != OverflowPolicy::Block || self.items.len() < self.capacity
+    }
+
+    pub(crate) fn push(&mut self, msg: TcpFuzzerMessage) {
+        self.offered += 1;
+        let msg = (Instant::now(), msg);
+
+        if self.items.len() < self.capacity {
+            self.items.push_back(msg);
+        } else {
+            match self.policy {
+                // caller checks `accepts_more`, so keep the message anyway
+                OverflowPolicy::Block => self.items.push_back(msg),
+                OverflowPolicy::DropOldest => {
+                    self.items.pop_front();
+                    self.items.push_back(msg);
+                    self.dropped += 1;
+                }
+                OverflowPolicy::Sample => {
+                    // reservoir sampling
+                    let slot = self.rand.below(self.offered);
+                    if let Some(item) = usize::try_from(slot)
+                        .ok()
+                        .and_then(|slot| self.items.get_mut(slot))
+                    {
+                        *item = msg;
+                    }
+                    self.dropped += 1;
+                }
+            }
+        }
+
+        self.peak = self.peak.max(self.items.len());
+    }
+
+    /// Queue reply to nn, it is never dropped
+    pub(crate) fn push_reply(&mut self, msg: TcpFuzzerMessage) {
+        self.replies.push_back((Instant::now(), msg));
+    }
+
+    /// Next message to send, replies go first
+    pub(crate) fn pop(&mut self) -> Option<TcpFuzzerMessage> {
+        if let Some(reply) = self.pop_reply() {
+            return Some(reply);
+        }
+        let (queued, msg) = self.items.pop_front()?;
+        if self.items.is_empty() {
+            self.offered = 0;
+        }
+        Some(self.sent(queued, msg))
+    }
+
+    /// Next reply to send, other messages stay queued
+    pub(crate) fn pop_reply(&mut self) -> Option<TcpFuzzerMessage> {
+        let (queued, msg) = self.replies.pop_front()?;
+        Some(self.sent(queued, msg))
+    }
+
+    /// Account the time message spent in the queue
+    fn sent(&mut self, queued: Instant, msg: TcpFuzzerMessage) -> TcpFuzzerMessage {
+        self.waited += queued.elapsed();
+        self.popped = self.popped.saturating_add(1);
+        msg
+    }
+
+    /// Messages lost outside of the queue
+    pub(crate) fn count_dropped(&mut self, dropped: u64) {
+        self.dropped += dropped;
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The longest queue since the last call
+    pub(crate) fn take_peak(&mut self) -> usize {
+        let peak = self.peak;
+        self.peak = self.items.len();
+        peak
+    }
+
+    /// Average time a message waited to be sent since the last call, `None` if none was sent
+    pub(crate) fn take_avg_latency(&mut self) -> Option<Duration> {
+        let avg = self.waited.checked_div(self.popped);
+        self.waited = Duration::ZERO;
+        self.popped = 0;
+        avg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutQueue, OverflowPolicy};
+    use crate::connector::messages::TcpFuzzerMessage;
+
+    fn push_all(queue: &mut OutQueue, seqs: std::ops::Range<u64>) {
+        for seq in seqs {
+            queue.push(TcpFuzzerMessage::Ping { seq });
+        }
+    }
+
+    fn pop_all(queue: &mut OutQueue) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|msg| match msg {
+                TcpFuzzerMessage::Ping { seq } => seq,
+                msg => panic!("unexpected {msg:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn block_keeps_everything() {
+        let mut queue = OutQueue::new(2, OverflowPolicy::Block);
+        push_all(&mut queue, 0..2);
+        assert!(!queue.accepts_more());
+        // pushed anyway, nothing is lost
+        push_all(&mut queue, 2..3);
+        assert_eq!(queue.dropped(), 0);
+        assert_eq!(queue.take_peak(), 3);
+        assert_eq!(pop_all(&mut queue), [0, 1, 2]);
+        assert!(queue.accepts_more());
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest() {
+        let mut queue = OutQueue::new(3, OverflowPolicy::DropOldest);
+        push_all(&mut queue, 0..10);
+        assert!(queue.accepts_more());
+        assert_eq!(queue.dropped(), 7);
+        assert_eq!(queue.take_peak(), 3);
+        assert_eq!(pop_all(&mut queue), [7, 8, 9]);
+    }
+
+    #[test]
+    fn sample_keeps_capacity_of_all() {
+        let mut queue = OutQueue::new(4, OverflowPolicy::Sample);
+        push_all(&mut queue, 0..100);
+        assert_eq!(queue.dropped(), 96);
+        assert_eq!(queue.take_peak(), 4);
+
+        let kept = pop_all(&mut queue);
+        assert_eq!(kept.len(), 4);
+        assert!(kept.iter().all(|seq| *seq < 100));
+        // the sample holds every message once at most
+        let mut unique = kept.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), kept.len());
+    }
+
+    #[test]
+    fn replies_bypass_full_queue() {
+        let mut queue = OutQueue::new(1, OverflowPolicy::Block);
+        push_all(&mut queue, 0..1);
+        assert!(!queue.accepts_more());
+        queue.push_reply(TcpFuzzerMessage::Ping { seq: 10 });
+        queue.push_reply(TcpFuzzerMessage::Ping { seq: 11 });
+        assert!(!queue.accepts_more());
+        assert_eq!(queue.dropped(), 0);
+        assert!(matches!(queue.pop_reply(), Some(TcpFuzzerMessage::Ping { seq: 10 })));
+        // replies go first
+        assert_eq!(pop_all(&mut queue), [11, 0]);
+    }
+
+    #[test]
+    fn drops_outside_are_counted() {
+        let mut queue = OutQueue::new(1, OverflowPolicy::DropOldest);
+        queue.count_dropped(5);
+        push_all(&mut queue, 0..2);
+        assert_eq!(queue.dropped(), 6);
+    }
+}
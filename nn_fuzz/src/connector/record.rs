@@ -0,0 +1,178 @@
+//! Session logs of nn connections
+//!
+//! The connector may write every frame it exchanges with a nn to a log, see `--nn-record-dir`.
+//! A log starts with [`SESSION_LOG_MAGIC`] followed by frames (see [`super::framing`]),
+//! each holding one postcard encoded [`RecordEntry`].
+//! Frames are kept as they were on the wire, in the [`super::messages::Encoding`] of the nn.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use libafl::bolts::current_time;
+use libafl::Error;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::framing::{recv_frame, send_frame};
+
+/// Starts every session log, the last bytes are the format version
+pub const SESSION_LOG_MAGIC: &[u8; 8] = b"nnsess01";
+
+/// Sessions started by this process, keeps names of simultaneous sessions apart
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Who sent the frame, logs are written by the fuzzer side
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ToNn,
+    FromNn,
+}
+
+/// One frame of the session
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordEntry {
+    /// Time since unix epoch
+    pub time: Duration,
+    pub direction: Direction,
+    /// The frame without its length prefix
+    pub frame: Vec<u8>,
+}
+
+/// Writes frames of one session as they are sent and received
+#[derive(Debug)]
+pub struct SessionRecorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl SessionRecorder {
+    /// New log in `dir`, named by the time the session started
+    ///
+    /// # Errors
+    ///    if the log cannot be created
+    ///
+    pub fn create(dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        let session = SESSIONS.fetch_add(1, Ordering::Relaxed);
+        let started = current_time().as_millis();
+        let path = dir.join(format!("session-{started}-{session}.nnlog"));
+
+        let mut out = BufWriter::new(File::create(&path)?);
+        out.write_all(SESSION_LOG_MAGIC)?;
+        out.flush()?;
+        Ok(Self { out, path })
+    }
+
+    /// Append frame, flushed at once so the log survives a crash of the fuzzer
+    ///
+    /// # Errors
+    ///    if the log cannot be written
+    ///
+    pub fn record(&mut self, direction: Direction, frame: &[u8]) -> Result<(), Error> {
+        self.write(&RecordEntry {
+            time: current_time(),
+            direction,
+            frame: frame.to_vec(),
+        })
+    }
+
+    fn write(&mut self, entry: &RecordEntry) -> Result<(), Error> {
+        send_frame(&mut self.out, &postcard::to_allocvec(entry)?, u32::MAX)?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// [`SessionRecorder`] of an async connection, frames are written on a blocking thread
+/// so runtime workers never wait for the disk
+#[derive(Debug)]
+pub struct RecordWriter {
+    entries: mpsc::UnboundedSender<RecordEntry>,
+}
+
+impl RecordWriter {
+    /// Hand the log to a blocking thread, must be called within tokio runtime.
+    /// The thread is done once the writer is dropped
+    #[must_use]
+    pub fn spawn(mut recorder: SessionRecorder) -> Self {
+        let (entries, mut queued) = mpsc::unbounded_channel::<RecordEntry>();
+        tokio::task::spawn_blocking(move || {
+            while let Some(entry) = queued.blocking_recv() {
+                if let Err(e) = recorder.write(&entry) {
+                    eprintln!("Session log {} is abandoned: {e}", recorder.path().display());
+                    return;
+                }
+            }
+        });
+        Self { entries }
+    }
+
+    /// Queue frame for the log, the time is taken now
+    ///
+    /// # Errors
+    ///    if the log was abandoned, the reason is already reported
+    ///
+    pub fn record(&self, direction: Direction, frame: &[u8]) -> Result<(), Error> {
+        let entry = RecordEntry {
+            time: current_time(),
+            direction,
+            frame: frame.to_vec(),
+        };
+        self.entries
+            .send(entry)
+            .map_err(|_| Error::illegal_state("Session log is abandoned"))
+    }
+}
+
+/// Entries of a session log in the order they were recorded
+#[derive(Debug)]
+pub struct SessionLog<R> {
+    input: R,
+}
+
+impl SessionLog<BufReader<File>> {
+    /// # Errors
+    ///    if the file cannot be read or is not a session log
+    ///
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> SessionLog<R> {
+    /// # Errors
+    ///    if the input does not start with [`SESSION_LOG_MAGIC`]
+    ///
+    pub fn new(mut input: R) -> Result<Self, Error> {
+        let mut magic = [0_u8; SESSION_LOG_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != SESSION_LOG_MAGIC {
+            return Err(Error::illegal_argument("Not a session log or unknown version"));
+        }
+        Ok(Self { input })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<RecordEntry>, Error> {
+        // the log ends between frames, anything else is a truncated log
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let frame = recv_frame(&mut self.input, u32::MAX)?;
+        Ok(Some(postcard::from_bytes(&frame)?))
+    }
+}
+
+impl<R: BufRead> Iterator for SessionLog<R> {
+    type Item = Result<RecordEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use libafl::bolts::current_time;
+use libafl::bolts::llmp::ClientId;
+
+use super::messages::{NnInfo, PeerKind};
+
+/// Connected neural networks and local fuzzers
+#[derive(Debug)]
+pub(crate) struct NnRegistry {
+    nns: Mutex<Vec<Arc<NnEntry>>>,
+    /// Connections so far of every nn name and version
+    connections: Mutex<HashMap<(String, String), u64>>,
+    /// How many peers may be connected at once
+    max_nns: usize,
+}
+
+/// One connected nn (or local fuzzer) with its accounting
+#[derive(Debug)]
+pub(crate) struct NnEntry {
+    kind: PeerKind,
+    nn_name: String,
+    nn_version: String,
+    /// `None` until the llmp client of nn is created
+    client_id: Mutex<Option<ClientId>>,
+    connected_at: Duration,
+    /// Earlier connections of nn with the same name and version
+    reconnects: u64,
+    /// Nn proved it knows the shared secret, its name is trusted
+    authenticated: bool,
+    /// Messages sent to nn
+    msgs_to_nn: AtomicU64,
+    /// Messages received from nn
+    msgs_from_nn: AtomicU64,
+    /// Inputs nn submitted to fuzzers
+    inputs_from_nn: AtomicU64,
+    /// Someone asked to drop this nn
+    kicked: AtomicBool,
+}
+
+impl NnEntry {
+    pub(crate) fn set_client_id(&self, client_id: ClientId) {
+        *self.client_id.lock().unwrap_or_else(PoisonError::into_inner) = Some(client_id);
+    }
+
+    pub(crate) fn client_id(&self) -> Option<ClientId> {
+        *self.client_id.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Both connections come from the same nn, same name and version
+    fn same_nn(&self, other: &NnEntry) -> bool {
+        self.kind == PeerKind::Nn
+            && other.kind == PeerKind::Nn
+            && self.nn_name == other.nn_name
+            && self.nn_version == other.nn_version
+    }
+
+    pub(crate) fn name(&self) -> String {
+        match self.kind {
+            PeerKind::Nn => format!("{} {}", self.nn_name, self.nn_version),
+            PeerKind::Local => "local fuzzer".to_string(),
+        }
+    }
+
+    pub(crate) fn count_to_nn(&self) {
+        self.msgs_to_nn.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_from_nn(&self) {
+        self.msgs_from_nn.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_inputs(&self, inputs: u64) {
+        self.inputs_from_nn.fetch_add(inputs, Ordering::Relaxed);
+    }
+
+    pub(crate) fn msgs_to_nn(&self) -> u64 {
+        self.msgs_to_nn.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn msgs_from_nn(&self) -> u64 {
+        self.msgs_from_nn.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn inputs_from_nn(&self) -> u64 {
+        self.inputs_from_nn.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    pub(crate) fn is_kicked(&self) -> bool {
+        self.kicked.load(Ordering::Relaxed)
+    }
+
+    /// `None` while the connection has no llmp client yet
+    fn info(&self) -> Option<NnInfo> {
+        Some(NnInfo {
+            client_id: self.client_id()?,
+            kind: self.kind,
+            nn_name: self.nn_name.clone(),
+            nn_version: self.nn_version.clone(),
+            connected_at: self.connected_at,
+            msgs_to_nn: self.msgs_to_nn(),
+            msgs_from_nn: self.msgs_from_nn(),
+        })
+    }
+}
+
+/// Removes nn from registry when connection is over
+#[derive(Debug)]
+pub(crate) struct NnRegistration {
+    registry: Arc<NnRegistry>,
+    entry: Arc<NnEntry>,
+}
+
+impl NnRegistration {
+    pub(crate) fn entry(&self) -> &NnEntry {
+        &self.entry
+    }
+}
+
+impl Drop for NnRegistration {
+    fn drop(&mut self) {
+        let mut nns = self.registry.nns.lock().unwrap_or_else(PoisonError::into_inner);
+        nns.retain(|entry| !Arc::ptr_eq(entry, &self.entry));
+    }
+}
+
+impl NnRegistry {
+    pub(crate) fn new(max_nns: usize) -> Self {
+        Self {
+            nns: Mutex::new(vec![]),
+            connections: Mutex::new(HashMap::new()),
+            max_nns,
+        }
+    }
+
+    /// Add new peer, `None` if there are too many peers already
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        kind: PeerKind,
+        nn_name: String,
+        nn_version: String,
+        authenticated: bool,
+    ) -> Option<NnRegistration> {
+        let mut nns = self.nns.lock().unwrap_or_else(PoisonError::into_inner);
+        if nns.len() >= self.max_nns {
+            return None;
+        }
+
+        let reconnects = {
+            let mut connections = self
+                .connections
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let seen = connections
+                .entry((nn_name.clone(), nn_version.clone()))
+                .or_insert(0);
+            *seen += 1;
+            *seen - 1
+        };
+
+        let entry = Arc::new(NnEntry {
+            kind,
+            nn_name,
+            nn_version,
+            client_id: Mutex::new(None),
+            connected_at: current_time(),
+            reconnects,
+            authenticated,
+            msgs_to_nn: AtomicU64::new(0),
+            msgs_from_nn: AtomicU64::new(0),
+            inputs_from_nn: AtomicU64::new(0),
+            kicked: AtomicBool::new(false),
+        });
+        nns.push(entry.clone());
+
+        Some(NnRegistration {
+            registry: self.clone(),
+            entry,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nns.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub(crate) fn max_nns(&self) -> usize {
+        self.max_nns
+    }
+
+    /// Connections with an llmp client, peers still connecting are left out
+    pub(crate) fn list(&self) -> Vec<NnInfo> {
+        self.nns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(|entry| entry.info())
+            .collect()
+    }
+
+    /// Ask connection of nn to shut down on behalf of `by`, `false` if there is no such nn.
+    /// Only authenticated nn may kick, and only connections of the same name and version
+    pub(crate) fn kick(&self, client_id: ClientId, by: &NnEntry) -> Result<bool, String> {
+        if by.kind != PeerKind::Nn {
+            return Err("Local fuzzers may not kick".to_string());
+        }
+        if !by.authenticated {
+            return Err("Only authenticated nn may kick".to_string());
+        }
+        let nns = self.nns.lock().unwrap_or_else(PoisonError::into_inner);
+        match nns.iter().find(|entry| entry.client_id() == Some(client_id)) {
+            Some(entry) if entry.same_nn(by) => {
+                entry.kicked.store(true, Ordering::Relaxed);
+                Ok(true)
+            }
+            Some(entry) => Err(format!(
+                "Client {client_id} is {}, not {}",
+                entry.name(),
+                by.name()
+            )),
+            None => Ok(false),
+        }
+    }
+}
@@ -1,34 +1,127 @@
 use tokio;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
+use tokio::time::MissedTickBehavior;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
 
-use std::io::{Read, Write};
-use std::net::SocketAddr;
-use std::net::TcpStream as StdTcpStream;
-use std::time::Duration;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self as std_mpsc, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use libafl::bolts::llmp::{ClientId, LlmpClient, LlmpConnection};
+use libafl::bolts::llmp::{ClientId, Flags, LlmpClient, LlmpConnection, Tag};
 use libafl::bolts::shmem::{ShMemProvider, StdShMemProvider};
+use libafl::monitors::UserStats;
+use libafl::prelude::{BytesInput, Event, HasBytesVec, LogSeverity};
 use libafl::Error;
 
+use rustls::ServerConfig;
 use serde::{Deserialize, Serialize};
 
+use crate::components::observers::decode_observers;
+
+use super::auth;
+use super::backlog::Backlog;
+use super::compression::{transcode, PayloadCodec};
+use super::framing::{negotiate_max_frame_size, FrameError, FramedLink, MIN_MAX_FRAME_SIZE};
 use super::messages::{
-    FuzzerDescription, TcpRemoteNewMessage, TcpRequest, TcpResponce, LLMP_FLAG_FROM_NN,
+    Capabilities, Compression, ControlCommand, Encoding, EventSubscription, FuzzerDescription,
+    NnControlAck, NnControlRequest, NnInputBatch, NnReward, NnTestcase, PeerKind, TcpFuzzerMessage,
+    TcpNnMessage, TcpRemoteNewMessage, TcpRequest, TcpResponce, CAP_OBSERVERS, CAP_STRUCTURED,
+    LLMP_FLAG_COMPRESSED, LLMP_FLAG_FROM_NN, LLMP_FLAG_INITIALIZED, LLMP_FLAG_NN_REPLY,
+    LLMP_TAG_EVENT_TO_BOTH, LLMP_TAG_NN_STATS_TICK, NN_CONTROL_ACK_TAG, NN_CONTROL_TAG,
+    NN_INPUT_BATCH_TAG, NN_INPUT_TAG, NN_REWARD_TAG, PROTOCOL_VERSION,
 };
+use super::queries::{Queries, StatsBoard};
+use super::record::SessionRecorder;
+use super::queue::{OutQueue, OverflowPolicy};
+use super::registry::{NnRegistration, NnRegistry};
+use super::tls::{self, TlsServerFiles};
 
-const _MAX_WORKING_THREADS: usize = 2;
-const _LLMP_NN_BLOCK_TIME: Duration = Duration::from_millis(3_000);
+/// Worker threads of the runtime all nn connections share
+pub const NN_WORKER_THREADS: usize = 3;
+/// How often llmp is checked for messages to forward, bounds the forwarding latency
+const LLMP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Llmp messages taken ahead of the connection, the rest waits in llmp
+const LLMP_READ_AHEAD: usize = 64;
+/// How often nn accounting is pushed to the broker monitor
+const NN_STATS_INTERVAL: Duration = Duration::from_secs(15);
+/// How often the broker is asked to publish the stats nn queries are answered with
+const STATS_TICK_INTERVAL: Duration = Duration::from_secs(5);
+/// Stored testcases sent between checks for peer messages
+const REPLAY_BATCH: usize = 64;
 
+/// Features this build of the fuzzer supports
+#[cfg(feature = "observer_feedback")]
+const CAPABILITIES: Capabilities = CAP_OBSERVERS | CAP_STRUCTURED;
+
+#[cfg(not(feature = "observer_feedback"))]
+const CAPABILITIES: Capabilities = CAP_STRUCTURED;
+
+/// Address nn connector listens on unless told otherwise
 #[cfg(feature = "bind_public")]
-const _BIND_ADDR: &str = "0.0.0.0";
+pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 
+/// Address nn connector listens on unless told otherwise
 #[cfg(not(feature = "bind_public"))]
-const _BIND_ADDR: &str = "127.0.0.1";
+pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Settings of the nn connector service
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    /// The port nn connect to
+    pub port: u16,
+    /// The address to listen on, IPv4 or IPv6
+    pub bind_addr: IpAddr,
+    /// The campaign description sent in the hello
+    pub fuzz_description: FuzzerDescription,
+    /// How many nn and local fuzzers may be connected at once
+    pub max_nns: usize,
+    /// Peers must sign the hello nonce with this secret, no authentication if `None`
+    pub secret: Option<Vec<u8>>,
+    /// Serve nn over TLS, plain tcp if `None`
+    pub tls: Option<TlsServerFiles>,
+    /// Listen on this unix socket instead of the port
+    pub unix_socket: Option<PathBuf>,
+    /// Peer is considered dead after this long silence, pinged three times within it
+    pub liveness_timeout: Duration,
+    /// How many messages may wait to be sent to each nn
+    pub queue_size: usize,
+    /// What to do with messages when the queue is full
+    pub overflow_policy: OverflowPolicy,
+    /// Largest frame accepted from nn, the smaller limit of both sides is used
+    pub max_frame_size: u32,
+    /// Corpus replayed to nn on request, nothing if `None`
+    pub corpus_dir: Option<PathBuf>,
+    /// Solutions replayed to nn on request, nothing if `None`
+    pub solutions_dir: Option<PathBuf>,
+    /// Every session is logged into its own file there, see [`super::record`]
+    pub record_dir: Option<PathBuf>,
+}
 
-#[derive(Debug)]
 enum Listener {
     Tcp(TcpListener),
+    /// TLS session is set up in the connection task
+    Tls(TcpListener, Arc<ServerConfig>),
+    /// Same host nn, access is controlled by socket file permissions
+    #[cfg(unix)]
+    Unix(UnixListener),
 }
 
 impl Listener {
@@ -38,6 +131,22 @@ impl Listener {
                 Ok(res) => ListenerStream::Tcp(res.0, res.1),
                 Err(_) => ListenerStream::Empty,
             },
+            Listener::Tls(inner, _) => match inner.accept().await {
+                Ok(res) => ListenerStream::Tls(res.0, res.1),
+                Err(_) => ListenerStream::Empty,
+            },
+            #[cfg(unix)]
+            Listener::Unix(inner) => match inner.accept().await {
+                Ok(res) => ListenerStream::Unix(res.0),
+                Err(_) => ListenerStream::Empty,
+            },
+        }
+    }
+
+    fn tls_config(&self) -> Option<Arc<ServerConfig>> {
+        match self {
+            Listener::Tls(_, config) => Some(config.clone()),
+            _ => None,
         }
     }
 }
@@ -45,257 +154,1417 @@ impl Listener {
 #[derive(Debug)]
 pub enum ListenerStream {
     Tcp(TcpStream, SocketAddr),
+    Tls(TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixStream),
     Empty,
 }
 
+/// Where the peer is connected from
+#[derive(Debug, Clone, Copy)]
+enum PeerAddr {
+    Net(SocketAddr),
+    Unix,
+}
+
+impl PeerAddr {
+    /// Peer runs on the same host
+    fn is_local(&self) -> bool {
+        match self {
+            // dual stack sockets see IPv4 peers as mapped IPv6 addresses
+            PeerAddr::Net(addr) => match addr.ip() {
+                IpAddr::V6(ip) => ip
+                    .to_ipv4_mapped()
+                    .map_or(ip.is_loopback(), |ip| ip.is_loopback()),
+                ip @ IpAddr::V4(_) => ip.is_loopback(),
+            },
+            PeerAddr::Unix => true,
+        }
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Net(addr) => write!(f, "{addr}"),
+            PeerAddr::Unix => write!(f, "unix socket"),
+        }
+    }
+}
+
+/// Connection with a peer after transport setup
+enum PeerStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for PeerStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PeerStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            PeerStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            PeerStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for PeerStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            PeerStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            PeerStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            PeerStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PeerStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            PeerStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            PeerStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PeerStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            PeerStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            PeerStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
 /// Info which NN provides before start
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct NNDescription {
     nn_name: String,
     nn_version: String,
+    /// Features supported by both sides
+    capabilities: Capabilities,
+    /// Events requested on handshake
+    subscription: EventSubscription,
+    /// Stored testcases requested on handshake
+    replay: bool,
 }
 
+/// The other side of an accepted connection
+#[derive(Debug, Clone)]
+enum Peer {
+    /// Remote neural network
+    Nn(NNDescription),
+    /// Out-of-llmp tool on the same host acting as a fuzzer (AFL++ wrapper, replay tool, ...)
+    Local,
+}
+
+impl Peer {
+    fn kind(&self) -> PeerKind {
+        match self {
+            Peer::Nn(_) => PeerKind::Nn,
+            Peer::Local => PeerKind::Local,
+        }
+    }
+
+    fn accepted(
+        &self,
+        client_id: ClientId,
+        max_frame_size: u32,
+        wire: &PayloadCodec,
+    ) -> TcpResponce {
+        let compression = wire.compression();
+        let compress_threshold = u32::try_from(wire.threshold()).unwrap_or(u32::MAX);
+        match self {
+            Peer::Nn(desc) => TcpResponce::RemoteNNAccepted {
+                client_id,
+                capabilities: desc.capabilities,
+                max_frame_size,
+                compression,
+                compress_threshold,
+            },
+            Peer::Local => TcpResponce::LocalAccepted {
+                client_id,
+                max_frame_size,
+                compression,
+                compress_threshold,
+            },
+        }
+    }
+
+    /// Events forwarded to the peer until it asks for others
+    fn subscription(&self) -> EventSubscription {
+        match self {
+            Peer::Nn(desc) => desc.subscription.clone(),
+            Peer::Local => EventSubscription::default(),
+        }
+    }
+
+    /// Peer wants stored testcases before live ones
+    fn wants_replay(&self) -> bool {
+        match self {
+            Peer::Nn(desc) => desc.replay,
+            Peer::Local => false,
+        }
+    }
+
+    /// Peer wants testcases as [`NnTestcase`]
+    fn structured(&self) -> bool {
+        match self {
+            Peer::Nn(desc) => desc.capabilities & CAP_STRUCTURED != 0,
+            Peer::Local => false,
+        }
+    }
+
+    /// Flags added to every message forwarded from the peer to the broker
+    fn forward_flags(&self) -> Flags {
+        match self {
+            Peer::Nn(_) => LLMP_FLAG_FROM_NN,
+            Peer::Local => LLMP_FLAG_INITIALIZED,
+        }
+    }
+}
+
+/// Accept nn connections until the broker exits, each connection is served by its own task
 ///
-/// # Panics
-///    panics if port is already used bu other process
+/// # Errors
+///    if port (or socket) is already used bu other process or TLS files are invalid
 ///
-pub async fn run_service(broker_port: u16, port: u16) {
-    let listener = Listener::Tcp(
-        TcpListener::bind((_BIND_ADDR, port))
-            .await
-            .unwrap_or_else(|_| panic!("NN connector: Cannot bind to port: {port}")),
+pub async fn run_service(
+    broker_port: u16,
+    options: ServiceOptions,
+    broker_events: broadcast::Sender<TcpRemoteNewMessage>,
+    stats: StatsBoard,
+) -> Result<(), Error> {
+    let listener = bind(&options).await?;
+
+    let queries = Queries::new(
+        stats,
+        options.corpus_dir.clone(),
+        options.solutions_dir.clone(),
     );
+    let options = Arc::new(options);
+    let registry = Arc::new(NnRegistry::new(options.max_nns));
 
-    let hello = TcpResponce::RemoteFuzzerHello {
-        fuzz_description: FuzzerDescription {
-            ec_size: crate::MAP_SIZE,
-            // TODO: get real values
-            instances: 0,
-            fuzz_target: String::new(),
-        },
-    };
+    thread::Builder::new()
+        .name("nn-stats-ticker".to_string())
+        .spawn(move || {
+            if let Err(e) = tick_stats(broker_port) {
+                eprintln!("NN connector: stats ticker stopped: {e:?}");
+            }
+        })?;
 
     loop {
-        match listener.accept().await {
-            ListenerStream::Tcp(mut stream, _) => {
-                match send_tcp_message(&mut stream, &hello).await {
-                    Ok(()) => {}
-                    Err(e) => {
-                        eprintln!("Error sending initial hello: {e:?}");
-                        continue;
-                    }
-                }
+        let stream = listener.accept().await;
+        if let ListenerStream::Empty = stream {
+            // Just ignore faults
+            continue;
+        }
 
-                let buf = match recv_tcp_message(&mut stream).await {
-                    Ok(buf) => buf,
+        let tls = listener.tls_config();
+        let options = options.clone();
+        let registry = registry.clone();
+        let broker_events = broker_events.clone();
+        let queries = queries.clone();
+        tokio::spawn(async move {
+            let (stream, addr) =
+                match transform_stream(stream, tls, options.liveness_timeout).await {
+                    Ok(res) => res,
                     Err(e) => {
-                        eprintln!("Error receiving from tcp: {e:?}");
-                        continue;
+                        eprintln!("NN connector: cannot accept connection: {e:?}");
+                        return;
                     }
                 };
+            let res = serve_peer(
+                stream,
+                addr,
+                broker_port,
+                &options,
+                &registry,
+                &broker_events,
+                queries,
+            )
+            .await;
+            if let Err(e) = res {
+                eprintln!("NN connector: connection with {addr} failed: {e:?}");
+            }
+        });
+    }
+}
 
-                let req = match buf.try_into() {
-                    Ok(req) => req,
-                    Err(e) => {
-                        eprintln!("Could not deserialize tcp message: {e:?}");
-                        continue;
-                    }
-                };
+async fn bind(options: &ServiceOptions) -> Result<Listener, Error> {
+    if let Some(path) = &options.unix_socket {
+        return bind_unix(path);
+    }
 
-                // handle connection
-                match req {
-                    // remote nn connection
-                    TcpRequest::RemoteNnHello {
-                        nn_name,
-                        nn_version,
-                    } => {
-                        tokio::task::spawn_blocking(move || -> Result<(), Error> {
-                            // prepare stream
-                            let mut stream = transform_stream(stream).expect("Cannot transform stream");
-
-                            let shmem_provider = StdShMemProvider::new()?;
-                            let mut nn_connector = NnConnector::new(shmem_provider, broker_port)?;
-                            
-                            let msg = TcpResponce::RemoteNNAccepted { client_id: nn_connector.id() };
-
-                            if let Err(_e) = send_tcp_msg(&mut stream, &msg) {
-                                println!("Error while sending accept packet");
-                            }
-
-                            let description = NNDescription {
-                                nn_name,
-                                nn_version,
-                            };
+    let addr = SocketAddr::new(options.bind_addr, options.port);
+    let inner = TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::unknown(format!("Cannot bind to {addr}: {e}")))?;
+    match &options.tls {
+        Some(files) => Ok(Listener::Tls(inner, tls::server_config(files)?)),
+        None => Ok(Listener::Tcp(inner)),
+    }
+}
 
-                            nn_connector.handle_connection(stream, &description);
-                            Ok(())
-                        });
-                    }
-                    // local fuzzer connection
-                    TcpRequest::LocalHello { client_id: _ } => {
-                        unimplemented!()
-                    }
-                }
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<Listener, Error> {
+    // socket file left by previous campaign
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let inner = UnixListener::bind(path)
+        .map_err(|e| Error::unknown(format!("Cannot bind to socket {}: {e}", path.display())))?;
+    Ok(Listener::Unix(inner))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path) -> Result<Listener, Error> {
+    Err(Error::unsupported(
+        "Unix sockets are not supported on this platform",
+    ))
+}
+
+/// Handshake with a new peer and forward messages until it disconnects
+async fn serve_peer(
+    stream: PeerStream,
+    addr: PeerAddr,
+    broker_port: u16,
+    options: &ServiceOptions,
+    registry: &Arc<NnRegistry>,
+    broker_events: &broadcast::Sender<TcpRemoteNewMessage>,
+    queries: Queries,
+) -> Result<(), Error> {
+    // peer may not send more than we accept even before it knows our limit
+    let mut stream = FramedLink::new(stream, options.max_frame_size, options.liveness_timeout);
+    if let Some(dir) = options.record_dir.clone() {
+        // the session matters more than its log
+        match blocking(move || SessionRecorder::create(&dir)).await.and_then(|res| res) {
+            Ok(recorder) => {
+                println!("NN connector: recording {addr} to {}", recorder.path().display());
+                stream.record_to(recorder);
             }
+            Err(e) => eprintln!("NN connector: cannot record {addr}: {e}"),
+        }
+    }
 
-            // Just ignore faults
-            ListenerStream::Empty => {
-                continue;
+    // every connection gets its own challenge
+    let nonce = match options.secret {
+        Some(_) => Some(auth::new_nonce()?),
+        None => None,
+    };
+
+    let hello = TcpResponce::RemoteFuzzerHello {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: CAPABILITIES,
+        max_frame_size: options.max_frame_size,
+        auth_nonce: nonce.clone(),
+        fuzz_description: options.fuzz_description.clone(),
+    };
+    // fuzzer speaks first and in postcard, nn of any version reads the hello
+    stream.send(&hello).await?;
+
+    // silent peer must not hold the task forever
+    let mut buf = match recv_handshake(&mut stream, options.liveness_timeout).await? {
+        Some(buf) => buf,
+        None => return Ok(()),
+    };
+    // nn speaking another encoding names it instead of the request, the hello is repeated
+    if let Some(encoding) = Encoding::from_name(&buf) {
+        stream.set_encoding(encoding);
+        stream.send(&hello).await?;
+        buf = match recv_handshake(&mut stream, options.liveness_timeout).await? {
+            Some(buf) => buf,
+            None => return Ok(()),
+        };
+    }
+
+    match stream.encoding().peek_protocol_version(&buf) {
+        Some(PROTOCOL_VERSION) => {}
+        version => {
+            let msg = TcpResponce::Error {
+                description: format!(
+                    "Unsupported protocol version {version:?}, fuzzer speaks version {PROTOCOL_VERSION}"
+                ),
+            };
+            return stream.send(&msg).await.map_err(Error::from);
+        }
+    }
+
+    let req: TcpRequest = stream.encoding().decode(&buf)?;
+    // payloads are transcoded from llmp gzip to whatever the peer wants
+    let wire_codec = PayloadCodec::new(
+        Compression::negotiate(req.compression()),
+        req.compress_threshold() as usize,
+    );
+    match negotiate_max_frame_size(options.max_frame_size, req.max_frame_size()) {
+        Some(max_frame_size) => stream.set_max_frame_size(max_frame_size),
+        None => {
+            let msg = TcpResponce::Error {
+                description: format!(
+                    "Frame size limit {} is below the minimum {MIN_MAX_FRAME_SIZE}",
+                    req.max_frame_size()
+                ),
+            };
+            return stream.send(&msg).await.map_err(Error::from);
+        }
+    }
+
+    if let (Some(secret), Some(nonce)) = (&options.secret, &nonce) {
+        if !req
+            .auth()
+            .map_or(false, |answer| auth::verify(secret, nonce, answer))
+        {
+            eprintln!("NN connector: {addr} failed authentication");
+            let msg = TcpResponce::Error {
+                description: "Authentication failed".to_string(),
+            };
+            return stream.send(&msg).await.map_err(Error::from);
+        }
+    }
+
+    // handle connection
+    let peer = match req {
+        // remote nn connection
+        TcpRequest::RemoteNnHello {
+            nn_name,
+            nn_version,
+            capabilities,
+            subscription,
+            replay,
+            ..
+        } => Peer::Nn(NNDescription {
+            nn_name,
+            nn_version,
+            capabilities: capabilities & CAPABILITIES,
+            subscription,
+            replay,
+        }),
+        // local fuzzer connection
+        TcpRequest::LocalHello { .. } => {
+            if !addr.is_local() {
+                let msg = TcpResponce::Error {
+                    description: "Local fuzzers must connect from the same host".to_string(),
+                };
+                return stream.send(&msg).await.map_err(Error::from);
             }
-        } // end loop
+            Peer::Local
+        }
+    };
+
+    // local fuzzers take their place among nn, the limit is on connections
+    let (nn_name, nn_version) = match &peer {
+        Peer::Nn(desc) => (desc.nn_name.clone(), desc.nn_version.clone()),
+        Peer::Local => (String::new(), String::new()),
+    };
+    let authenticated = options.secret.is_some();
+    let registration = match registry.register(peer.kind(), nn_name, nn_version, authenticated) {
+        Some(registration) => registration,
+        None => {
+            let msg = TcpResponce::Error {
+                description: format!(
+                    "Too many peers connected, at most {} allowed",
+                    registry.max_nns()
+                ),
+            };
+            return stream.send(&msg).await.map_err(Error::from);
+        }
+    };
+
+    let nn_connector = NnConnector::new(
+        broker_port,
+        broker_events.subscribe(),
+        registry.clone(),
+        registration,
+        options.liveness_timeout,
+        OutQueue::new(options.queue_size, options.overflow_policy),
+        queries,
+        wire_codec,
+    )
+    .await?;
+
+    let backlog = if peer.wants_replay() {
+        let corpus_dir = options.corpus_dir.clone();
+        let solutions_dir = options.solutions_dir.clone();
+        let scan = move || Backlog::scan(corpus_dir.as_deref(), solutions_dir.as_deref());
+        Some(blocking(scan).await?)
+    } else {
+        None
+    };
+
+    nn_connector.handle_connection(stream, &peer, backlog).await;
+    Ok(())
+}
+
+/// Next handshake frame of the peer, `None` if it is larger than the limit, peer is told so
+async fn recv_handshake(
+    stream: &mut FramedLink<PeerStream>,
+    timeout: Duration,
+) -> Result<Option<Vec<u8>>, Error> {
+    match stream.recv_timeout(timeout).await {
+        Ok(buf) => Ok(Some(buf)),
+        Err(e @ FrameError::TooLarge { .. }) => {
+            let msg = TcpResponce::Error {
+                description: format!("Protocol error: {e}"),
+            };
+            stream.send(&msg).await?;
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
-struct NnConnector<SP: ShMemProvider + 'static> {
-    mock_fuzzer: LlmpClient<SP>,
+/// Llmp client of one connection. Its pages must not move between threads,
+/// so it lives on a thread of its own and the connection talks to it over channels
+struct MockFuzzer {
+    id: ClientId,
+    /// Messages for llmp, the client leaves llmp once it is closed
+    outgoing: std_mpsc::Sender<TcpRemoteNewMessage>,
+    /// Messages of other llmp clients, closed if the client failed
+    incoming: mpsc::Receiver<TcpRemoteNewMessage>,
 }
 
-impl<SP> NnConnector<SP>
-where
-    SP: ShMemProvider + 'static,
-{
-    fn new(shmem_provider: SP, broker_port: u16) -> Result<Self, Error> {
-        let client = LlmpConnection::client_on_port(shmem_provider, broker_port)?;
-        if let LlmpConnection::IsClient { client } = client {
-            Ok(Self {
-                mock_fuzzer: client,
-            })
-        } else {
-            unreachable!()
-        }
+impl MockFuzzer {
+    /// Join llmp of the broker, the handshake is done on the client thread.
+    /// The client waits up to `release_timeout` for the broker when it leaves
+    async fn connect(broker_port: u16, release_timeout: Duration) -> Result<Self, Error> {
+        let (outgoing, to_llmp) = std_mpsc::channel();
+        let (from_llmp, incoming) = mpsc::channel(LLMP_READ_AHEAD);
+        let (ready, connected) = oneshot::channel();
+        thread::Builder::new()
+            .name("nn-llmp-client".to_string())
+            .spawn(move || {
+                run_mock_fuzzer(broker_port, release_timeout, ready, &to_llmp, from_llmp);
+            })?;
+
+        let id = connected
+            .await
+            .map_err(|_| Error::illegal_state("Llmp client thread is gone"))??;
+        Ok(Self {
+            id,
+            outgoing,
+            incoming,
+        })
+    }
+
+    /// Queue message for llmp, the client thread sends it
+    ///
+    /// # Errors
+    ///    if the client failed
+    ///
+    fn send(&self, tag: Tag, flags: Flags, payload: Vec<u8>) -> Result<(), Error> {
+        let msg = TcpRemoteNewMessage {
+            client_id: self.id,
+            tag,
+            flags,
+            payload,
+        };
+        self.outgoing
+            .send(msg)
+            .map_err(|_| Error::illegal_state("Llmp client is gone"))
+    }
+}
+
+struct NnConnector {
+    mock_fuzzer: MockFuzzer,
+    /// Codec of llmp payloads
+    compressor: PayloadCodec,
+    /// Codec of payloads agreed on with the peer
+    wire_codec: PayloadCodec,
+    /// Events handled by the broker itself, they never reach llmp clients
+    broker_events: broadcast::Receiver<TcpRemoteNewMessage>,
+    /// All connected nn
+    registry: Arc<NnRegistry>,
+    /// Our place in registry
+    registration: NnRegistration,
+    /// Peer is dead after this long silence
+    liveness_timeout: Duration,
+    /// Messages waiting to be sent to peer
+    queue: OutQueue,
+    /// Answers to stats and corpus queries
+    queries: Queries,
+}
+
+impl NnConnector {
+    #[allow(clippy::too_many_arguments)]
+    async fn new(
+        broker_port: u16,
+        broker_events: broadcast::Receiver<TcpRemoteNewMessage>,
+        registry: Arc<NnRegistry>,
+        registration: NnRegistration,
+        liveness_timeout: Duration,
+        queue: OutQueue,
+        queries: Queries,
+        wire_codec: PayloadCodec,
+    ) -> Result<Self, Error> {
+        let mock_fuzzer = MockFuzzer::connect(broker_port, liveness_timeout).await?;
+        registration.entry().set_client_id(mock_fuzzer.id);
+        Ok(Self {
+            mock_fuzzer,
+            compressor: PayloadCodec::llmp(),
+            wire_codec,
+            broker_events,
+            registry,
+            registration,
+            liveness_timeout,
+            queue,
+            queries,
+        })
     }
 
-    fn handle_connection(&mut self, stream: StdTcpStream, _desc: &NNDescription) {
+    /// Serve the peer until it is gone, then leave llmp whatever happened
+    async fn handle_connection(
+        mut self,
+        stream: FramedLink<PeerStream>,
+        peer: &Peer,
+        backlog: Option<Backlog>,
+    ) {
         let mut stream = stream;
-        stream
-            .set_read_timeout(Some(_LLMP_NN_BLOCK_TIME))
-            .expect("Failed to set tcp stream timeout");
+        let reason = match self.serve(&mut stream, peer, backlog).await {
+            Ok(reason) => reason,
+            Err(e) => {
+                let msg = TcpFuzzerMessage::Error {
+                    description: format!("Fuzzer side error: {e}"),
+                };
+                let _ = stream.send(&msg).await;
+                format!("error: {e:?}")
+            }
+        };
+
+        self.shutdown(&reason);
+    }
+
+    /// Returns why the connection is over, errors are fatal for this connection only
+    async fn serve(
+        &mut self,
+        stream: &mut FramedLink<PeerStream>,
+        peer: &Peer,
+        mut backlog: Option<Backlog>,
+    ) -> Result<String, Error> {
+        let ping_interval = self.liveness_timeout / 3;
+        // pings, reports and replay are checked this often while nothing else happens
+        let mut housekeeping = tokio::time::interval(LLMP_POLL_INTERVAL);
+        housekeeping.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         let id = self.id();
+        let forward_flags = peer.forward_flags();
+        let structured = peer.structured();
+        let mut subscription = peer.subscription();
+
+        let accepted = peer.accepted(id, stream.max_frame_size(), &self.wire_codec);
+        stream.send(&accepted).await?;
+
+        let name = self.registration.entry().name();
+        println!("NN connector: {name} connected as client {id}");
+        self.report("nn", UserStats::String(name))?;
+        let mut last_report = Instant::now();
+        // any message from peer proves it is alive
+        let mut last_seen = Instant::now();
+        let mut last_ping = Instant::now();
+        let mut ping_seq = 0_u64;
+
         loop {
+            if self.is_kicked() {
+                let msg = TcpFuzzerMessage::Error {
+                    description: "Kicked by other nn".to_string(),
+                };
+                let _ = stream.send(&msg).await;
+                return Ok("kicked by other nn".to_string());
+            }
+
+            if last_seen.elapsed() > self.liveness_timeout {
+                return Ok(format!("no messages for {:?}", last_seen.elapsed()));
+            }
+
+            if last_ping.elapsed() >= ping_interval {
+                ping_seq += 1;
+                let ping = TcpFuzzerMessage::Ping { seq: ping_seq };
+                if let Err(e) = stream.send(&ping).await {
+                    return Ok(format!("connection lost: {e}"));
+                }
+                last_ping = Instant::now();
+            }
+
+            if last_report.elapsed() >= NN_STATS_INTERVAL {
+                self.report_stats(stream)?;
+                last_report = Instant::now();
+            }
+
             // first, forward all data we have.
+            // History goes before live messages, they wait in llmp meanwhile.
+            // Replies do not wait, nn may ask for them during the replay
+            let forwarded = match backlog.take() {
+                Some(pending) => match replay_some(stream, pending).await {
+                    Ok(rest) => {
+                        backlog = rest;
+                        self.send_replies(stream).await
+                    }
+                    Err(e) => Err(e),
+                },
+                None => self.forward_all(stream, &subscription, structured).await,
+            };
+            match forwarded {
+                Ok(()) => {}
+                Err(e) if is_connection_error(&e) => {
+                    return Ok(format!("connection lost: {e}"));
+                }
+                Err(e) => return Err(e),
+            }
 
-            while let Some((client_id, tag, flags, payload)) = self
-                .mock_fuzzer
-                .recv_buf_with_flags()
-                .expect("Error reading from local page!")
-            {
-                if client_id == id {
-                    // println!(
-                    //     "Ignored message we probably sent earlier (same id), TAG: {:x}",
-                    //     tag
-                    // );
+            // Then wait for whatever comes first: a frame of the peer, a llmp message,
+            // an event the broker kept for itself, or the next housekeeping tick.
+            // Either way we are back in the loop within the poll interval,
+            // so forwarding and pings are never held up
+            let live = backlog.is_none();
+            let val = tokio::select! {
+                frame = stream.recv() => frame,
+                msg = self.mock_fuzzer.incoming.recv(), if live && self.queue.accepts_more() => {
+                    match msg {
+                        Some(msg) => self.enqueue(&subscription, structured, msg),
+                        None => return Err(Error::illegal_state("Llmp client is gone")),
+                    }
+                    continue;
+                }
+                // replies of fuzzers come this way, they must not wait for a full queue
+                event = self.broker_events.recv() => {
+                    match event {
+                        Ok(msg) => self.enqueue_broker_event(&subscription, structured, msg),
+                        Err(RecvError::Lagged(skipped)) => self.queue.count_dropped(skipped),
+                        Err(RecvError::Closed) => return Ok("broker is gone".to_string()),
+                    }
+                    continue;
+                }
+                _ = housekeeping.tick() => continue,
+            };
+            let val = match val {
+                Ok(val) => val,
+                // the rest of the frame is still in the stream, cannot go on
+                Err(e @ FrameError::TooLarge { .. }) => {
+                    let msg = TcpFuzzerMessage::Error {
+                        description: format!("Protocol error: {e}"),
+                    };
+                    let _ = stream.send(&msg).await;
+                    return Ok(format!("protocol error: {e}"));
+                }
+                Err(e) => return Ok(format!("connection lost: {e}")),
+            };
+            last_seen = Instant::now();
+
+            self.registration.entry().count_from_nn();
+
+            // frames are length prefixed, so the stream is still in sync after a bad one
+            let msg: TcpNnMessage = match stream.encoding().decode(&val) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    eprintln!("NN connector: illegal message from client {id}: {e:?}");
+                    let msg = TcpFuzzerMessage::Error {
+                        description: format!("Illegal message: {e}"),
+                    };
+                    stream.send(&msg).await?;
                     continue;
                 }
+            };
+
+            let reply = match msg {
+                TcpNnMessage::NewMessage(mut msg) => {
+                    if let Err(e) = transcode(&self.wire_codec, &self.compressor, &mut msg) {
+                        let msg = TcpFuzzerMessage::Error {
+                            description: format!("Illegal payload: {e}"),
+                        };
+                        stream.send(&msg).await?;
+                        continue;
+                    }
+                    // an event the broker cannot read must not get into llmp at all
+                    let inputs = match injected_inputs(&self.compressor, &msg) {
+                        Ok(inputs) => inputs,
+                        Err(e) => {
+                            eprintln!("NN connector: cannot decode message of {id}: {e}");
+                            let msg = TcpFuzzerMessage::Error {
+                                description: format!("Illegal event: {e}"),
+                            };
+                            stream.send(&msg).await?;
+                            continue;
+                        }
+                    };
+                    self.registration.entry().count_inputs(inputs);
+                    self.mock_fuzzer
+                        .send(msg.tag, msg.flags | forward_flags, msg.payload)?;
+                    None
+                }
+                TcpNnMessage::SubmitInputs {
+                    first_request_id,
+                    inputs,
+                } => {
+                    self.submit_inputs(first_request_id, inputs, forward_flags)?;
+                    None
+                }
+                TcpNnMessage::Subscribe(new_subscription) => {
+                    subscription = new_subscription;
+                    None
+                }
+                TcpNnMessage::ListNns => Some(TcpFuzzerMessage::NnList(self.registry.list())),
+                TcpNnMessage::KickNn { client_id } => Some(self.kick(client_id)),
+                TcpNnMessage::Ping { seq } => Some(TcpFuzzerMessage::Pong { seq }),
+                TcpNnMessage::Pong { .. } => None,
+                TcpNnMessage::GetStats => Some(TcpFuzzerMessage::Stats(self.queries.stats())),
+                TcpNnMessage::GetCorpusEntry { kind, name } => {
+                    let queries = self.queries.clone();
+                    let (name, testcase) = blocking(move || {
+                        let testcase = queries.corpus_entry(kind, &name);
+                        (name, testcase)
+                    })
+                    .await?;
+                    Some(TcpFuzzerMessage::CorpusEntry {
+                        kind,
+                        name,
+                        testcase,
+                    })
+                }
+                TcpNnMessage::Control {
+                    command_id,
+                    targets,
+                    command,
+                } => self.control(command_id, targets, command)?,
+            };
+
+            if let Some(reply) = reply {
+                if let Err(e) = stream.send(&reply).await {
+                    return Ok(format!("connection lost: {e}"));
+                }
+            }
+        } // end loop
+    }
+
+    /// Queue llmp messages and events the broker kept for itself, then send them to nn
+    async fn forward_all(
+        &mut self,
+        stream: &mut FramedLink<PeerStream>,
+        subscription: &EventSubscription,
+        structured: bool,
+    ) -> Result<(), Error> {
+        // with blocking policy the rest stays in llmp until nn catches up
+        while self.queue.accepts_more() {
+            let msg = match self.mock_fuzzer.incoming.try_recv() {
+                Ok(msg) => msg,
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    return Err(Error::illegal_state("Llmp client is gone"))
+                }
+            };
+            self.enqueue(subscription, structured, msg);
+        }
+
+        // the broker does not wait for nn, whatever is left in the channel would be lost anyway
+        loop {
+            let msg = match self.broker_events.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Lagged(skipped)) => {
+                    self.queue.count_dropped(skipped);
+                    continue;
+                }
+                Err(_) => break,
+            };
+            self.enqueue_broker_event(subscription, structured, msg);
+        }
+
+        while let Some(msg) = self.queue.pop() {
+            self.send_queued(stream, &msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Send queued replies, other messages stay in the queue
+    async fn send_replies(&mut self, stream: &mut FramedLink<PeerStream>) -> Result<(), Error> {
+        while let Some(msg) = self.queue.pop_reply() {
+            self.send_queued(stream, &msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Send message taken from the queue, the one too large for the peer is dropped
+    async fn send_queued(
+        &mut self,
+        stream: &mut FramedLink<PeerStream>,
+        msg: &TcpFuzzerMessage,
+    ) -> Result<(), Error> {
+        match stream.send(msg).await {
+            Ok(()) => {}
+            // nothing was written, the stream is still in sync
+            Err(e @ FrameError::TooLarge { .. }) => {
+                eprintln!("NN connector: dropping message for client {}: {e}", self.id());
+                self.queue.count_dropped(1);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.registration.entry().count_to_nn();
+        Ok(())
+    }
+
+    /// Pass command of nn to fuzzers, fuzzers ack it themselves.
+    /// Invalid command is refused right away, fuzzers never see it
+    fn control(
+        &mut self,
+        command_id: u64,
+        targets: Option<Vec<ClientId>>,
+        command: ControlCommand,
+    ) -> Result<Option<TcpFuzzerMessage>, Error> {
+        if let Err(description) = command.validate() {
+            return Ok(Some(TcpFuzzerMessage::ControlAck {
+                command_id,
+                fuzzer_id: None,
+                error: Some(description),
+            }));
+        }
+
+        let request = NnControlRequest {
+            command_id,
+            client_id: self.id(),
+            targets,
+            command,
+        };
+        self.fire(&Event::CustomBuf {
+            tag: NN_CONTROL_TAG.to_string(),
+            buf: postcard::to_allocvec(&request)?,
+        })?;
+        Ok(None)
+    }
+
+    /// Broadcast inputs of nn as if it sent [`NnInputBatch`] itself
+    fn submit_inputs(
+        &mut self,
+        first_request_id: u64,
+        inputs: Vec<Vec<u8>>,
+        flags: Flags,
+    ) -> Result<(), Error> {
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        let count = inputs.len() as u64;
+        let batch = NnInputBatch {
+            first_request_id,
+            client_id: self.id(),
+            inputs,
+        };
+        let event = Event::CustomBuf {
+            tag: NN_INPUT_BATCH_TAG.to_string(),
+            buf: postcard::to_allocvec(&batch)?,
+        };
+        self.fire_with_flags(&event, flags)?;
+
+        self.registration.entry().count_inputs(count);
+        Ok(())
+    }
+
+    /// Queue message for nn if it is subscribed to it, replies if they are for this nn
+    fn enqueue(
+        &mut self,
+        subscription: &EventSubscription,
+        structured: bool,
+        msg: TcpRemoteNewMessage,
+    ) {
+        let client_id = msg.client_id;
+        let reply = is_reply(&msg);
+        let shaped = if reply {
+            reply_for(&self.compressor, self.id(), &msg)
+        } else {
+            apply_subscription(&self.compressor, subscription, structured, msg)
+        };
+        let shaped = shaped.and_then(|msg| match msg {
+            Some(TcpFuzzerMessage::NewMessage(mut msg)) => {
+                transcode(&self.compressor, &self.wire_codec, &mut msg)?;
+                Ok(Some(TcpFuzzerMessage::NewMessage(msg)))
+            }
+            msg => Ok(msg),
+        });
+        match shaped {
+            Ok(Some(msg)) if reply => self.queue.push_reply(msg),
+            Ok(Some(msg)) => self.queue.push(msg),
+            Ok(None) => {}
+            Err(e) => eprintln!("Dropping malformed message from {client_id}: {e:?}"),
+        }
+    }
+
+    /// Queue event the broker kept for itself. Replies always get through,
+    /// other events are dropped if the queue is full
+    fn enqueue_broker_event(
+        &mut self,
+        subscription: &EventSubscription,
+        structured: bool,
+        msg: TcpRemoteNewMessage,
+    ) {
+        if is_reply(&msg) || self.queue.accepts_more() {
+            self.enqueue(subscription, structured, msg);
+        } else {
+            self.queue.count_dropped(1);
+        }
+    }
+
+    /// Leave llmp after the peer is gone, the client thread waits for the broker to free
+    /// our pages
+    fn shutdown(mut self, reason: &str) {
+        let id = self.id();
+        let message = format!(
+            "NN connector: {} (client {id}) disconnected: {reason}",
+            self.registration.entry().name()
+        );
+        println!("{message}");
+
+        if let Err(e) = self.log(LogSeverity::Warn, message) {
+            eprintln!("NN connector: cannot log to broker: {e:?}");
+        }
+        // the client sends what is left and leaves once the channel is closed
+        drop(self);
+    }
+
+    /// Kick on request of the peer, refused unless it may do it
+    fn kick(&self, client_id: ClientId) -> TcpFuzzerMessage {
+        match self.registry.kick(client_id, self.registration.entry()) {
+            Ok(found) => TcpFuzzerMessage::Kicked { client_id, found },
+            Err(description) => TcpFuzzerMessage::Error { description },
+        }
+    }
+
+    fn is_kicked(&self) -> bool {
+        self.registration.entry().is_kicked()
+    }
+
+    /// Push nn accounting to the broker monitor
+    fn report_stats(&mut self, stream: &FramedLink<PeerStream>) -> Result<(), Error> {
+        let depth = self.queue.take_peak();
+        let drops = self.queue.dropped();
+        self.report("nn_queue_depth", UserStats::Number(depth as u64))?;
+        self.report("nn_queue_drops", UserStats::Number(drops))?;
+        // nothing sent since the last report is a stall worth seeing
+        if let Some(latency) = self.queue.take_avg_latency() {
+            let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+            self.report("nn_forward_latency_us", UserStats::Number(micros))?;
+        }
+
+        let entry = self.registration.entry();
+        let (to_nn, from_nn) = (entry.msgs_to_nn(), entry.msgs_from_nn());
+        let (inputs, reconnects) = (entry.inputs_from_nn(), entry.reconnects());
+
+        self.report("nn_msgs_to_nn", UserStats::Number(to_nn))?;
+        self.report("nn_msgs_from_nn", UserStats::Number(from_nn))?;
+        self.report("nn_bytes_to_nn", UserStats::Number(stream.bytes_sent()))?;
+        self.report("nn_bytes_from_nn", UserStats::Number(stream.bytes_received()))?;
+        self.report("nn_inputs", UserStats::Number(inputs))?;
+        self.report("nn_reconnects", UserStats::Number(reconnects))?;
+        self.report(
+            "nn_connected",
+            UserStats::Ratio(self.registry.len() as u64, self.registry.max_nns() as u64),
+        )
+    }
+
+    /// Show value in the broker monitor as user stats of this connector
+    fn report(&mut self, name: &str, value: UserStats) -> Result<(), Error> {
+        self.fire(&Event::UpdateUserStats {
+            name: name.to_string(),
+            value,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Print message in the broker log
+    fn log(&mut self, severity_level: LogSeverity, message: String) -> Result<(), Error> {
+        self.fire(&Event::Log {
+            severity_level,
+            message,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Send event to the broker as if this connector was a fuzzer
+    fn fire(&mut self, event: &Event<BytesInput>) -> Result<(), Error> {
+        self.fire_with_flags(event, LLMP_FLAG_INITIALIZED)
+    }
+
+    fn fire_with_flags(&mut self, event: &Event<BytesInput>, flags: Flags) -> Result<(), Error> {
+        let serialized = postcard::to_allocvec(event)?;
+
+        match self.compressor.compress(&serialized)? {
+            Some(comp_buf) => self.mock_fuzzer.send(
+                LLMP_TAG_EVENT_TO_BOTH,
+                flags | LLMP_FLAG_COMPRESSED,
+                comp_buf,
+            ),
+            None => self
+                .mock_fuzzer
+                .send(LLMP_TAG_EVENT_TO_BOTH, flags, serialized),
+        }
+    }
+
+    fn id(&self) -> ClientId {
+        self.mock_fuzzer.id
+    }
+}
+
+/// Body of the llmp client thread, it leaves llmp once the connection closes `to_llmp`
+fn run_mock_fuzzer(
+    broker_port: u16,
+    release_timeout: Duration,
+    ready: oneshot::Sender<Result<ClientId, Error>>,
+    to_llmp: &std_mpsc::Receiver<TcpRemoteNewMessage>,
+    from_llmp: mpsc::Sender<TcpRemoteNewMessage>,
+) {
+    let mut client = match connect_llmp(broker_port) {
+        Ok(client) => client,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let id = client.sender.id;
+    // a connection gone meanwhile has closed `to_llmp`, we just leave then
+    let _ = ready.send(Ok(id));
+
+    if let Err(e) = pass_messages(&mut client, to_llmp, &from_llmp) {
+        eprintln!("NN connector: llmp client {id} failed: {e:?}");
+    }
+    // the connection learns the client is gone
+    drop(from_llmp);
+
+    if let Err(e) = client.sender.send_exiting() {
+        eprintln!("NN connector: cannot leave llmp: {e:?}");
+        return;
+    }
+    // dead broker never answers, the pages are gone with the process then
+    let started = Instant::now();
+    while !client.safe_to_unmap() {
+        if started.elapsed() > release_timeout {
+            eprintln!("NN connector: broker did not release client {id}");
+            return;
+        }
+        thread::sleep(LLMP_POLL_INTERVAL);
+    }
+}
 
-                // We got a new message! Forward...
-                send_tcp_msg(
-                    &mut stream,
-                    &TcpRemoteNewMessage {
+/// Ask the broker to publish its stats every [`STATS_TICK_INTERVAL`], the broker runs only
+/// when a message arrives. Broadcasts are read and dropped, unread pages are kept forever
+fn tick_stats(broker_port: u16) -> Result<(), Error> {
+    let mut client = connect_llmp(broker_port)?;
+    let mut last_tick: Option<Instant> = None;
+    loop {
+        while client.recv_buf()?.is_some() {}
+        if last_tick.map_or(true, |at| at.elapsed() >= STATS_TICK_INTERVAL) {
+            client.send_buf(LLMP_TAG_NN_STATS_TICK, &[])?;
+            last_tick = Some(Instant::now());
+        }
+        thread::sleep(LLMP_POLL_INTERVAL);
+    }
+}
+
+fn connect_llmp(broker_port: u16) -> Result<LlmpClient<StdShMemProvider>, Error> {
+    let shmem_provider = StdShMemProvider::new()?;
+    match LlmpConnection::client_on_port(shmem_provider, broker_port)? {
+        LlmpConnection::IsClient { client } => Ok(client),
+        LlmpConnection::IsBroker { .. } => {
+            Err(Error::illegal_state("Expected llmp client, got broker"))
+        }
+    }
+}
+
+/// Move messages between llmp and the connection until the connection is gone
+fn pass_messages(
+    client: &mut LlmpClient<StdShMemProvider>,
+    to_llmp: &std_mpsc::Receiver<TcpRemoteNewMessage>,
+    from_llmp: &mpsc::Sender<TcpRemoteNewMessage>,
+) -> Result<(), Error> {
+    let id = client.sender.id;
+    // taken from llmp when the connection had no room for it
+    let mut pending = None;
+
+    loop {
+        let mut idle = true;
+        loop {
+            match to_llmp.try_recv() {
+                Ok(msg) => client.send_buf_with_flags(msg.tag, msg.flags, &msg.payload)?,
+                Err(std_mpsc::TryRecvError::Empty) => break,
+                Err(std_mpsc::TryRecvError::Disconnected) => return Ok(()),
+            }
+            idle = false;
+        }
+
+        // with blocking policy the rest stays in llmp until nn catches up
+        loop {
+            let msg = match pending.take() {
+                Some(msg) => msg,
+                None => match client.recv_buf_with_flags()? {
+                    // messages we sent come back to us
+                    Some((client_id, ..)) if client_id == id => continue,
+                    Some((client_id, tag, flags, payload)) => TcpRemoteNewMessage {
                         client_id,
                         tag,
                         flags,
                         payload: payload.to_vec(),
                     },
-                )
-                .expect("Error sending message to nn");
+                    None => break,
+                },
+            };
+            match from_llmp.try_send(msg) {
+                Ok(()) => idle = false,
+                Err(TrySendError::Full(msg)) => {
+                    pending = Some(msg);
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => return Ok(()),
             }
+        }
 
-            // Then, see if we can receive something.
-            // We set a timeout on the receive earlier.
-            // This makes sure we will still forward our own stuff.
-            // Forwarding happens between each recv, too, as simplification.
-            // We ignore errors completely as they may be timeout, or stream closings.
-            // Instead, we catch stream close when/if we next try to send.
-            if let Ok(val) = recv_tcp_msg(&mut stream) {
-                let msg: TcpRemoteNewMessage = val
-                    .try_into()
-                    .expect("Illegal message received from nn - shutting down.");
-
-                self.mock_fuzzer
-                    .send_buf_with_flags(msg.tag, msg.flags | LLMP_FLAG_FROM_NN, &msg.payload)
-                    .expect("B2B: Error forwarding message. Exiting.");
+        if idle {
+            match to_llmp.recv_timeout(LLMP_POLL_INTERVAL) {
+                Ok(msg) => client.send_buf_with_flags(msg.tag, msg.flags, &msg.payload)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
             }
-        } // end loop
-    }
-
-    fn id(&self) -> ClientId {
-        self.mock_fuzzer.sender.id
+        }
     }
 }
 
 /*
 * Helper functions
 */
-async fn send_tcp_message<T>(stream: &mut TcpStream, msg: &T) -> Result<(), Error>
-where
-    T: Serialize,
-{
-    let msg = postcard::to_allocvec(msg)?;
-    if let Ok(len) = u32::try_from(msg.len()) {
-        let size_bytes = len.to_be_bytes();
-        stream.write_all(&size_bytes).await?;
-        stream.write_all(&msg).await?;
-        Ok(())
+
+/// Filter message by the nn subscription and shape it for nn, `None` if nn is not interested in it
+fn apply_subscription(
+    compressor: &PayloadCodec,
+    subscription: &EventSubscription,
+    structured: bool,
+    mut msg: TcpRemoteNewMessage,
+) -> Result<Option<TcpFuzzerMessage>, Error> {
+    if !subscription.accepts_client(msg.client_id) {
+        return Ok(None);
+    }
+    if subscription.is_everything() && !structured {
+        return Ok(Some(TcpFuzzerMessage::NewMessage(msg)));
+    }
+
+    // the tag is whatever the sender set, the event itself decides
+    let mut event = match decode_llmp_event(compressor, &msg) {
+        Ok(event) => event,
+        // not an event, only nn asking for everything gets it
+        Err(_) if msg.tag != LLMP_TAG_EVENT_TO_BOTH => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if !subscription.accepts(&event) {
+        return Ok(None);
+    }
+
+    if structured {
+        if let Some(testcase) = structured_testcase(msg.client_id, &event, subscription.observers) {
+            return Ok(Some(TcpFuzzerMessage::Testcase(testcase)));
+        }
+    }
+
+    if let Event::NewTestcase { observers_buf, .. } = &mut event {
+        if !subscription.observers && observers_buf.take().is_some() {
+            let serialized = postcard::to_allocvec(&event)?;
+            (msg.flags, msg.payload) = match compressor.compress(&serialized)? {
+                Some(comp_buf) => (msg.flags | LLMP_FLAG_COMPRESSED, comp_buf),
+                None => (msg.flags & !LLMP_FLAG_COMPRESSED, serialized),
+            };
+        }
+    }
+
+    Ok(Some(TcpFuzzerMessage::NewMessage(msg)))
+}
+
+/// Event carried by llmp message
+fn decode_llmp_event(
+    compressor: &PayloadCodec,
+    msg: &TcpRemoteNewMessage,
+) -> Result<Event<BytesInput>, Error> {
+    if msg.flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+        Ok(postcard::from_bytes(&compressor.decompress(&msg.payload)?)?)
     } else {
-        Err(Error::illegal_state(format!(
-            "Trying to send a tcp message > u32 (size: {})",
-            msg.len()
-        )))
+        Ok(postcard::from_bytes(&msg.payload)?)
+    }
+}
+
+/// Inputs submitted by nn message, zero for anything but
+/// [`super::messages::NnInputRequest`] and [`super::messages::NnInputBatch`].
+/// Fuzzers read events whatever the tag, so they are counted whatever the tag
+///
+/// # Errors
+///    if message tagged as event is not one, the broker would fail on it
+///
+fn injected_inputs(
+    compressor: &PayloadCodec,
+    msg: &TcpRemoteNewMessage,
+) -> Result<u64, Error> {
+    let event = match decode_llmp_event(compressor, msg) {
+        Ok(event) => event,
+        Err(_) if msg.tag != LLMP_TAG_EVENT_TO_BOTH => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    match event {
+        Event::CustomBuf { tag, .. } if tag == NN_INPUT_TAG => Ok(1),
+        Event::CustomBuf { tag, buf } if tag == NN_INPUT_BATCH_TAG => {
+            let batch: NnInputBatch = postcard::from_bytes(&buf)?;
+            Ok(batch.inputs.len() as u64)
+        }
+        _ => Ok(0),
     }
 }
 
-fn transform_stream(stream: TcpStream) -> Result<StdTcpStream, std::io::Error> {
-    let std_tcp_stream = stream.into_std()?;
-    std_tcp_stream.set_nonblocking(false)?;
-    Ok(std_tcp_stream)
+/// Message is a reply of a fuzzer to some nn
+fn is_reply(msg: &TcpRemoteNewMessage) -> bool {
+    msg.flags & LLMP_FLAG_NN_REPLY == LLMP_FLAG_NN_REPLY
 }
 
-fn send_tcp_msg<T>(stream: &mut StdTcpStream, msg: &T) -> Result<(), Error>
-where
-    T: Serialize,
-{
-    let msg = postcard::to_allocvec(msg)?;
-    if let Ok(len) = u32::try_from(msg.len()) {
-        let size_bytes = len.to_be_bytes();
-        stream.write_all(&size_bytes)?;
-        stream.write_all(&msg)?;
-        Ok(())
-    } else {
-        return Err(Error::illegal_state(format!(
-            "Trying to send message a tcp message > u32! (size: {})",
-            msg.len()
-        )));
+/// Reward or control ack carried by message, `None` if it is for another nn
+fn reply_for(
+    compressor: &PayloadCodec,
+    nn_id: ClientId,
+    msg: &TcpRemoteNewMessage,
+) -> Result<Option<TcpFuzzerMessage>, Error> {
+    let (tag, buf) = match decode_llmp_event(compressor, msg)? {
+        Event::<BytesInput>::CustomBuf { tag, buf } => (tag, buf),
+        _ => return Err(Error::illegal_argument("reply is not a custom buffer")),
+    };
+
+    match tag.as_str() {
+        NN_REWARD_TAG => {
+            let reward: NnReward = postcard::from_bytes(&buf)?;
+            Ok((reward.client_id == nn_id).then_some(TcpFuzzerMessage::Reward {
+                fuzzer_id: msg.client_id,
+                reward,
+            }))
+        }
+        NN_CONTROL_ACK_TAG => {
+            let ack: NnControlAck = postcard::from_bytes(&buf)?;
+            Ok((ack.client_id == nn_id).then_some(TcpFuzzerMessage::ControlAck {
+                command_id: ack.command_id,
+                fuzzer_id: Some(msg.client_id),
+                error: ack.error,
+            }))
+        }
+        _ => Err(Error::illegal_argument(format!("unknown reply tag {tag}"))),
     }
 }
 
-async fn recv_tcp_message(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
-    let mut size_bytes = [0u8; 4];
-    stream.read_exact(&mut size_bytes).await?;
-    let size = u32::from_be_bytes(size_bytes);
-    let mut bytes = Vec::new();
-    bytes.resize(size as usize, 0);
+/// Flatten new testcase event, `None` for other events
+fn structured_testcase(
+    client_id: ClientId,
+    event: &Event<BytesInput>,
+    with_coverage: bool,
+) -> Option<NnTestcase> {
+    match event {
+        Event::NewTestcase {
+            input,
+            observers_buf,
+            exit_kind,
+            corpus_size,
+            time,
+            executions,
+            ..
+        } => {
+            // clients without observer feedback, or other observer layout
+            let trace = observers_buf
+                .as_deref()
+                .and_then(|buf| decode_observers(buf).ok());
 
-    stream
-        .read_exact(&mut bytes)
-        .await
-        .expect("Failed to read message body");
+            Some(NnTestcase {
+                client_id,
+                input: input.bytes().to_vec(),
+                exit_kind: exit_kind.into(),
+                exec_time: trace.as_ref().and_then(|trace| trace.exec_time),
+                executions: *executions as u64,
+                corpus_size: *corpus_size as u64,
+                time: *time,
+                coverage: trace
+                    .filter(|_| with_coverage)
+                    .map(|trace| trace.coverage),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Send the next part of stored testcases, the rest of them or `None` once all are sent.
+/// Testcases are read on a blocking thread, the runtime never waits for the disk
+async fn replay_some(
+    stream: &mut FramedLink<PeerStream>,
+    backlog: Backlog,
+) -> Result<Option<Backlog>, Error> {
+    let (backlog, batch) = blocking(move || {
+        let mut backlog = backlog;
+        let batch: Vec<_> = backlog.by_ref().take(REPLAY_BATCH).collect();
+        (backlog, batch)
+    })
+    .await?;
+
+    for stored in batch {
+        match stream.send(&TcpFuzzerMessage::Stored(stored)).await {
+            Ok(()) => {}
+            // nothing was written, the stream is still in sync
+            Err(e @ FrameError::TooLarge { .. }) => {
+                eprintln!("NN connector: skipping stored testcase: {e}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
 
-    Ok(bytes)
+    if !backlog.is_finished() {
+        return Ok(Some(backlog));
+    }
+    stream.send(&backlog.done()).await?;
+    Ok(None)
 }
 
-/// Receive one message of `u32` len and `[u8; len]` bytes
-fn recv_tcp_msg(stream: &mut StdTcpStream) -> Result<Vec<u8>, Error> {
-    // Always receive one be u32 of size, then the command.
+/// Run file system work on a blocking thread, runtime workers serve connections meanwhile
+async fn blocking<T, F>(work: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Error::unknown(format!("Blocking task failed: {e}")))
+}
 
-    let mut size_bytes = [0_u8; 4];
-    stream.read_exact(&mut size_bytes)?;
-    let size = u32::from_be_bytes(size_bytes);
-    let mut bytes = vec![];
-    bytes.resize(size as usize, 0_u8);
+/// Peer went away, not our fault
+fn is_connection_error(e: &Error) -> bool {
+    matches!(e, Error::File(..))
+}
 
-    stream
-        .read_exact(&mut bytes)
-        .expect("Failed to read message body");
-    Ok(bytes)
+/// Finish transport setup, TLS handshake must be over within `timeout`
+async fn transform_stream(
+    stream: ListenerStream,
+    tls: Option<Arc<ServerConfig>>,
+    timeout: Duration,
+) -> Result<(PeerStream, PeerAddr), Error> {
+    match stream {
+        ListenerStream::Tcp(stream, addr) => Ok((PeerStream::Tcp(stream), PeerAddr::Net(addr))),
+        ListenerStream::Tls(stream, addr) => {
+            let config = tls.ok_or_else(|| Error::illegal_state("TLS is not configured"))?;
+            let accept = TlsAcceptor::from(config).accept(stream);
+            let stream = match tokio::time::timeout(timeout, accept).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    return Err(Error::unknown(format!("TLS handshake with {addr} failed: {e}")))
+                }
+                Err(_) => {
+                    return Err(Error::unknown(format!("TLS handshake with {addr} timed out")))
+                }
+            };
+            Ok((PeerStream::Tls(Box::new(stream)), PeerAddr::Net(addr)))
+        }
+        #[cfg(unix)]
+        ListenerStream::Unix(stream) => Ok((PeerStream::Unix(stream), PeerAddr::Unix)),
+        ListenerStream::Empty => Err(Error::illegal_state("No connection to transform")),
+    }
 }
@@ -0,0 +1,267 @@
+//! TLS transport of nn connections
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libafl::Error;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+
+/// Certificate files of the fuzzer side
+#[derive(Debug, Clone)]
+pub struct TlsServerFiles {
+    /// PEM certificate chain of the fuzzer
+    pub cert: PathBuf,
+    /// PEM private key of the fuzzer
+    pub key: PathBuf,
+    /// Require nn to present certificate signed by this PEM CA
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Certificate files of the nn side
+#[derive(Debug, Clone)]
+pub struct TlsClientFiles {
+    /// PEM CA (or self-signed certificate) the fuzzer certificate is checked against
+    pub ca: PathBuf,
+    /// PEM certificate chain of the nn, if fuzzer asks for it
+    pub cert: Option<PathBuf>,
+    /// PEM private key of the nn
+    pub key: Option<PathBuf>,
+    /// Name in the fuzzer certificate
+    pub server_name: String,
+}
+
+/// # Errors
+///    if files are unreadable or do not contain valid certificate and key
+pub fn server_config(files: &TlsServerFiles) -> Result<Arc<ServerConfig>, Error> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &files.client_ca {
+        Some(ca) => builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(
+            load_roots(ca)?,
+        )),
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(load_certs(&files.cert)?, load_private_key(&files.key)?)
+        .map_err(|e| Error::illegal_argument(format!("Invalid TLS certificate: {e}")))?;
+    Ok(Arc::new(config))
+}
+
+/// # Errors
+///    if files are unreadable or do not contain valid certificates and key
+pub fn client_config(files: &TlsClientFiles) -> Result<Arc<ClientConfig>, Error> {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(load_roots(&files.ca)?);
+
+    let config = match (&files.cert, &files.key) {
+        (Some(cert), Some(key)) => builder
+            .with_single_cert(load_certs(cert)?, load_private_key(key)?)
+            .map_err(|e| Error::illegal_argument(format!("Invalid TLS certificate: {e}")))?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => {
+            return Err(Error::illegal_argument(
+                "TLS certificate and key must be given together",
+            ))
+        }
+    };
+    Ok(Arc::new(config))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    if certs.is_empty() {
+        return Err(Error::illegal_argument(format!(
+            "No certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKey, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key)) => {
+                return Ok(PrivateKey(key))
+            }
+            Some(_) => {}
+            None => {
+                return Err(Error::illegal_argument(format!(
+                    "No private key found in {}",
+                    path.display()
+                )))
+            }
+        }
+    }
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|e| Error::illegal_argument(format!("Invalid CA certificate: {e}")))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+    use rustls::{ClientConfig, ClientConnection, Connection, ServerConfig, ServerConnection};
+
+    use super::{client_config, server_config, TlsClientFiles, TlsServerFiles};
+
+    /// PEM files of one test
+    struct Pems(PathBuf);
+
+    impl Pems {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("nn_fuzz-{}-{test}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// Certificate and key of `cert`, signed by `signer` or self-signed
+        fn write(
+            &self,
+            name: &str,
+            cert: &Certificate,
+            signer: Option<&Certificate>,
+        ) -> [PathBuf; 2] {
+            let pem = match signer {
+                Some(signer) => cert.serialize_pem_with_signer(signer).unwrap(),
+                None => cert.serialize_pem().unwrap(),
+            };
+            let cert_path = self.path(&format!("{name}.pem"));
+            let key_path = self.path(&format!("{name}.key"));
+            fs::write(&cert_path, pem).unwrap();
+            fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+            [cert_path, key_path]
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for Pems {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn localhost() -> Certificate {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    fn ca() -> Certificate {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        Certificate::from_params(params).unwrap()
+    }
+
+    fn nn() -> Certificate {
+        Certificate::from_params(CertificateParams::new(vec!["nn".to_string()])).unwrap()
+    }
+
+    fn client_files(ca: &Path, cert: Option<[PathBuf; 2]>) -> TlsClientFiles {
+        let (cert, key) = match cert {
+            Some([cert, key]) => (Some(cert), Some(key)),
+            None => (None, None),
+        };
+        TlsClientFiles {
+            ca: ca.to_path_buf(),
+            cert,
+            key,
+            server_name: "localhost".to_string(),
+        }
+    }
+
+    /// Hand the pending TLS records of one side to the other
+    fn transfer(from: &mut Connection, to: &mut Connection) -> Result<(), rustls::Error> {
+        let mut records = vec![];
+        while from.wants_write() {
+            from.write_tls(&mut records).unwrap();
+        }
+        let mut records = &records[..];
+        while !records.is_empty() {
+            to.read_tls(&mut records).unwrap();
+            to.process_new_packets()?;
+        }
+        Ok(())
+    }
+
+    /// Handshake in memory, the first error of either side
+    fn handshake(
+        server: Arc<ServerConfig>,
+        client: Arc<ClientConfig>,
+    ) -> Result<(), rustls::Error> {
+        let mut server = Connection::from(ServerConnection::new(server)?);
+        let name = "localhost".try_into().unwrap();
+        let mut client = Connection::from(ClientConnection::new(client, name)?);
+
+        // a few flights at most
+        for _ in 0..8 {
+            if !client.is_handshaking() && !server.is_handshaking() {
+                return Ok(());
+            }
+            transfer(&mut client, &mut server)?;
+            transfer(&mut server, &mut client)?;
+        }
+        panic!("handshake does not finish");
+    }
+
+    #[test]
+    fn self_signed_handshake() {
+        let pems = Pems::new("self-signed");
+        let [cert, key] = pems.write("fuzzer", &localhost(), None);
+
+        let server = server_config(&TlsServerFiles {
+            cert: cert.clone(),
+            key,
+            client_ca: None,
+        })
+        .unwrap();
+        let client = client_config(&client_files(&cert, None)).unwrap();
+
+        handshake(server, client).unwrap();
+    }
+
+    #[test]
+    fn client_cert_must_be_signed_by_the_ca() {
+        let pems = Pems::new("client-ca");
+        let [cert, key] = pems.write("fuzzer", &localhost(), None);
+        let authority = ca();
+        let [ca_cert, _] = pems.write("ca", &authority, None);
+        let signed = pems.write("signed", &nn(), Some(&authority));
+        let stranger = pems.write("stranger", &nn(), Some(&ca()));
+
+        let server = server_config(&TlsServerFiles {
+            cert: cert.clone(),
+            key,
+            client_ca: Some(ca_cert),
+        })
+        .unwrap();
+
+        let client = client_config(&client_files(&cert, Some(signed))).unwrap();
+        handshake(server.clone(), client).unwrap();
+
+        let client = client_config(&client_files(&cert, Some(stranger))).unwrap();
+        assert!(handshake(server.clone(), client).is_err());
+
+        // no certificate at all
+        let client = client_config(&client_files(&cert, None)).unwrap();
+        assert!(handshake(server, client).is_err());
+    }
+}
@@ -4,6 +4,10 @@ use std::path::{Path, PathBuf};
 use libafl::prelude::*;
 
 use crate::cli::FuzzerOptions;
+use crate::connector::auth::load_secret;
+use crate::connector::tls::{self, TlsServerFiles};
+use crate::connector::messages::FuzzerDescription;
+use crate::connector::server::ServiceOptions;
 
 #[cfg_attr(target_family = "windows", path = "win.rs")]
 #[cfg_attr(target_family = "unix", path = "unix.rs")]
@@ -26,9 +30,76 @@ fn check_options(options: &FuzzerOptions) -> Result<(), Error> {
         }
         // other checks
     }
+    if options.map_size == 0 {
+        return Err(Error::illegal_argument("Coverage map size must not be zero"));
+    }
     Ok(())
 }
 
+/// Collect campaign info for the neural network
+fn describe_campaign(options: &FuzzerOptions) -> Result<FuzzerDescription, Error> {
+    let tokens = Tokens::new().add_from_files(&options.tokens)?;
+
+    Ok(FuzzerDescription {
+        // the fuzzers observe the whole shared map
+        ec_size: options.map_size,
+        instances: options.cores.ids.len(),
+        fuzz_target: options.executable.to_string_lossy().into_owned(),
+        fuzz_args: options.args.clone(),
+        timeout: u64::try_from(options.timeout.as_millis()).unwrap_or(u64::MAX),
+        input_max_length: options.input_max_length,
+        tokens: tokens.tokens().to_vec(),
+    })
+}
+
+/// Settings of the nn service from command line
+fn nn_service(options: &FuzzerOptions) -> Result<ServiceOptions, Error> {
+    Ok(ServiceOptions {
+        port: options.client_port,
+        bind_addr: options.client_bind,
+        fuzz_description: describe_campaign(options)?,
+        max_nns: options.max_nn_clients,
+        secret: nn_secret(options)?,
+        tls: nn_tls(options)?,
+        unix_socket: options.client_socket.clone(),
+        liveness_timeout: options.nn_timeout,
+        queue_size: options.nn_queue_size,
+        overflow_policy: options.nn_overflow,
+        max_frame_size: options.nn_max_frame,
+        corpus_dir: Some(options.queue.clone()),
+        solutions_dir: Some(options.output.clone()),
+        record_dir: options.nn_record_dir.clone(),
+    })
+}
+
+/// Shared secret of nn connections from command line
+fn nn_secret(options: &FuzzerOptions) -> Result<Option<Vec<u8>>, Error> {
+    match (&options.nn_secret, &options.nn_secret_file) {
+        (Some(secret), _) if secret.is_empty() => {
+            Err(Error::illegal_argument("Shared secret is empty"))
+        }
+        (Some(secret), _) => Ok(Some(secret.as_bytes().to_vec())),
+        (None, Some(path)) => load_secret(path).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// TLS files of nn connections from command line, checked to be valid
+fn nn_tls(options: &FuzzerOptions) -> Result<Option<TlsServerFiles>, Error> {
+    match (&options.tls_cert, &options.tls_key) {
+        (Some(cert), Some(key)) => {
+            let files = TlsServerFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: options.tls_client_ca.clone(),
+            };
+            tls::server_config(&files)?;
+            Ok(Some(files))
+        }
+        _ => Ok(None),
+    }
+}
+
 fn load_tokens<EM, S>(dicts: &[PathBuf], state: &mut S, mgr: &mut EM) -> Result<(), Error>
 where
     EM: EventFirer<State = S>,
@@ -1,11 +1,12 @@
 use super::{
     current_nanos, feedback_or, feedback_or_fast, havoc_mutations, load_tokens, mutate_args,
-    ondisk, tokens_mutations, tuple_list, AsMutSlice, BytesInput, CachedOnDiskCorpus, Corpus,
-    CrashFeedback, EventConfig, ForkserverExecutor, Fuzzer, FuzzerOptions, HasCorpus,
-    HitcountsMapObserver, IndexesLenTimeMinimizerScheduler, LlmpRestartingEventManager,
-    MaxMapFeedback, Merge, MultiMonitor, OnDiskCorpus, QueueScheduler, RandBytesGenerator, ShMem,
-    ShMemProvider, StdMapObserver, StdRand, StdScheduledMutator, StdShMemProvider, StdState,
-    TimeFeedback, TimeObserver, TimeoutFeedback, TimeoutForkserverExecutor,
+    nn_service, ondisk, tokens_mutations, tuple_list, AsMutSlice, BytesInput,
+    CachedOnDiskCorpus, Corpus, CrashFeedback, EventConfig, ForkserverExecutor, Fuzzer,
+    FuzzerOptions, HasCorpus, HasCustomBufHandlers, HasEventManagerId, HitcountsMapObserver,
+    IndexesLenTimeMinimizerScheduler, LlmpRestartingEventManager, MaxMapFeedback, Merge,
+    MultiMonitor, OnDiskCorpus, QueueScheduler, RandBytesGenerator, ShMem, ShMemProvider,
+    StdMapObserver, StdRand, StdShMemProvider, StdState, TimeFeedback, TimeObserver,
+    TimeoutFeedback, TimeoutForkserverExecutor,
 };
 
 #[cfg(not(feature = "observer_feedback"))]
@@ -17,6 +18,10 @@ use crate::components::fuzzer::HeavyFuzzer;
 #[cfg(feature = "tui")]
 use super::tui::TuiMonitor;
 
+use crate::components::control::{apply_nn_control, fuzz_loop};
+use crate::components::mutators::ControlledScheduledMutator;
+use crate::components::nn_inputs::queue_nn_input;
+use crate::components::observers::{EDGES_OBSERVER_NAME, TIME_OBSERVER_NAME};
 use crate::components::stages::CustomMutationalStage;
 use crate::error::Error;
 use crate::launcher::Launcher;
@@ -32,6 +37,9 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
     #[cfg(not(feature = "tui"))]
     let monitor = MultiMonitor::new(|s| println!("{s}"));
 
+    // Info for nn handshake
+    let nn_service = nn_service(options)?;
+
     // AFL++ compatible shmem provider
     let shmem_provider = StdShMemProvider::new()?;
 
@@ -39,15 +47,16 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
                           mut mgr: LlmpRestartingEventManager<_, _>,
                           core_id: usize| {
         let mut shmem_provider = StdShMemProvider::new()?;
-        let mut shmem = shmem_provider.new_shmem(crate::MAP_SIZE).unwrap();
+        let mut shmem = shmem_provider.new_shmem(options.map_size).unwrap();
         // provide shmid for forkserver
         shmem.write_to_env("__AFL_SHM_ID").unwrap();
 
         // Component: Observers
-        let edges_observer =
-            HitcountsMapObserver::new(unsafe { StdMapObserver::new("edges", shmem.as_mut_slice()) });
+        let edges_observer = HitcountsMapObserver::new(unsafe {
+            StdMapObserver::new(EDGES_OBSERVER_NAME, shmem.as_mut_slice())
+        });
 
-        let time_observer = TimeObserver::new("time");
+        let time_observer = TimeObserver::new(TIME_OBSERVER_NAME);
 
         // Component: Feedback
         // Rate input as interesting or not
@@ -110,12 +119,23 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
         // LOAD TOKENS
         load_tokens(options.tokens.as_slice(), &mut state, &mut mgr)?;
 
+        // Inputs of nn are evaluated by the stage, it answers with rewards
+        mgr.add_custom_buf_handler(Box::new(queue_nn_input));
+
+        // Commands of nn are applied to state, the stages read them
+        let client_id = u32::try_from(mgr.mgr_id().id).unwrap_or(u32::MAX);
+        mgr.add_custom_buf_handler(Box::new(move |state: &mut _, tag: &String, buf: &[u8]| {
+            apply_nn_control(state, tag, buf, client_id)
+        }));
+
         // Component: Scheduler
         let scheduler = IndexesLenTimeMinimizerScheduler::new(QueueScheduler::new());
 
         // Component: Real Fuzzer
         #[cfg(feature = "observer_feedback")]
         let mut fuzzer = HeavyFuzzer::new(scheduler, feedback, objective);
+        #[cfg(feature = "observer_feedback")]
+        fuzzer.set_sparse_coverage(options.nn_sparse_coverage);
 
         #[cfg(not(feature = "observer_feedback"))]
         let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
@@ -133,6 +153,7 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
             .shmem_provider(&mut shmem_provider)
             .arg_input_file(format!(".cur_input_{core_id}"))
             .parse_afl_cmdline(harness_args)
+            // nn connector decodes observers as `FuzzObservers`, keep the order
             .build(tuple_list!(time_observer, edges_observer))
             .unwrap();
 
@@ -169,13 +190,15 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
 
         // MAINTAIN FUZZER STAGES
         // ======================
-        let mutator =
-            StdScheduledMutator::with_max_stack_pow(havoc_mutations().merge(tokens_mutations()), 6);
+        let mutator = ControlledScheduledMutator::with_max_stack_pow(
+            havoc_mutations().merge(tokens_mutations()),
+            6,
+        );
 
         let mut stages = tuple_list!(CustomMutationalStage::new(mutator));
 
         // RUUUN!
-        fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
+        fuzz_loop(&mut fuzzer, &mut stages, &mut executor, &mut state, &mut mgr)?;
         Ok(())
     }; // run_client closure
 
@@ -191,7 +214,7 @@ pub(super) fn fuzz(options: &FuzzerOptions) -> Result<(), Error> {
         .stdout_file(options.stdout.as_deref())
         .spawn_broker(!options.no_broker)
         .spawn_nn_client(options.spawn_client)
-        .remote_nn_port(options.client_port)
+        .nn_service(nn_service)
         .build()
         .launch()
 }
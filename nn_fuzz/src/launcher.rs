@@ -19,6 +19,7 @@ use serde::de::DeserializeOwned;
 
 use typed_builder::TypedBuilder;
 
+use crate::connector::server::ServiceOptions;
 use crate::llmp::NnRestartingMgr;
 
 #[cfg(unix)]
@@ -59,9 +60,8 @@ where
     /// Should spawn nn client as separate llmp client
     #[builder(default = false)]
     spawn_nn_client: bool,
-    /// The `port` of nn
-    #[builder()]
-    remote_nn_port: u16,
+    /// Port, campaign description and the rest of nn service settings
+    nn_service: ServiceOptions,
     /// If this launcher should spawn a new `broker` on `[Self::broker_port]` (default).
     /// The reason you may not want this is, if you already have a [`Launcher`]
     /// with a different configuration (for the same target) running on this machine.
@@ -85,7 +85,8 @@ where
             .field("broker_port", &self.broker_port)
             .field("core", &self.cores)
             .field("spawn_broker", &self.spawn_broker)
-            .field("remote_broker_addr", &self.remote_nn_port)
+            .field("remote_broker_addr", &self.nn_service.port)
+            .field("nn_bind_addr", &self.nn_service.bind_addr)
             .field("stdout_file", &self.stdout_file)
             .finish_non_exhaustive()
     }
@@ -175,7 +176,7 @@ where
                 .broker_port(self.broker_port)
                 .configuration(self.configuration)
                 .spawn_nn_client(self.spawn_nn_client)
-                .remote_nn_port(self.remote_nn_port)
+                .nn_service(self.nn_service.clone())
                 .build()
                 .launch()?;
 
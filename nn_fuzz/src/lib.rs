@@ -15,5 +15,7 @@ mod llmp;
 mod launcher;
 mod utils;
 
+pub use components::observers;
+
 
 
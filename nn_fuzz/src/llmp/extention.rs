@@ -22,10 +22,17 @@ use libafl::prelude::{
 use libafl::state::{HasClientPerfMonitor, HasExecutions, HasMetadata, UsesState};
 use libafl::{Error, EvaluatorObservers, ExecutionProcessor};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
-use crate::connector::server::run_service;
+use crate::connector::messages::{
+    TcpRemoteNewMessage, LLMP_FLAG_NN_REPLY, LLMP_TAG_EVENT_TO_BOTH, LLMP_TAG_NN_STATS_TICK,
+    NN_CONTROL_ACK_TAG, NN_REWARD_TAG,
+};
+use crate::connector::queries::StatsBoard;
+use crate::connector::server::{run_service, ServiceOptions, NN_WORKER_THREADS};
 
-const LLMP_TAG_EVENT_TO_BOTH: Tag = 0x002B_0741;
+/// How many broker-handled events are kept for slow nn connections
+const NN_EVENTS_CAPACITY: usize = 1024;
 
 /// The minimum buffer size at which to compress LLMP IPC messages.
 const COMPRESS_THRESHOLD: usize = 1024;
@@ -342,18 +349,29 @@ where
         Ok(LlmpNnBroker { port, broker })
     }
 
-    pub fn spawn_client(&mut self, port: u16) {
+    pub fn spawn_client(
+        &mut self,
+        options: ServiceOptions,
+        nn_events: broadcast::Sender<TcpRemoteNewMessage>,
+        stats: StatsBoard,
+    ) {
         let broker_port = self.port;
 
         thread::spawn(move || {
-            tokio::runtime::Builder::new_multi_thread()
-                .worker_threads(3)
+            let runtime = match tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(NN_WORKER_THREADS)
                 .enable_all()
                 .build()
-                .unwrap()
-                .block_on(async move {
-                    run_service(broker_port, port).await;
-                });
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    eprintln!("NN connector: cannot start runtime: {e}");
+                    return;
+                }
+            };
+            if let Err(e) = runtime.block_on(run_service(broker_port, options, nn_events, stats)) {
+                eprintln!("NN connector: service stopped: {e:?}");
+            }
         });
     }
 }
@@ -367,6 +385,10 @@ where
     monitor: MT,
     llmp: LlmpNnBroker<SP>,
     compressor: GzipCompressor,
+    /// Events handled by the broker, passed to nn connections as they never reach llmp clients
+    nn_events: Option<broadcast::Sender<TcpRemoteNewMessage>>,
+    /// Monitor numbers nn connections answer queries with
+    stats: StatsBoard,
     phantom: PhantomData<I>,
 }
 
@@ -381,33 +403,62 @@ where
             monitor,
             llmp: LlmpNnBroker::create_attach_to_tcp(shmem_provider, port)?,
             compressor: GzipCompressor::new(COMPRESS_THRESHOLD),
+            nn_events: None,
+            stats: StatsBoard::default(),
             phantom: PhantomData,
         })
     }
 
-    pub fn spawn_client(&mut self, port: u16) {
-        self.llmp.spawn_client(port);
+    pub fn spawn_client(&mut self, options: ServiceOptions) {
+        let (nn_events, _) = broadcast::channel(NN_EVENTS_CAPACITY);
+        self.llmp.spawn_client(options, nn_events.clone(), self.stats.clone());
+        self.nn_events = Some(nn_events);
     }
 
     #[allow(clippy::unnecessary_wraps)]
     pub fn broker_loop(&mut self) -> Result<(), Error> {
         let monitor = &mut self.monitor;
         let compressor = &self.compressor;
+        let nn_events = &self.nn_events;
+        let stats = &self.stats;
         self.llmp.loop_forever(
             &mut |client_id: u32, tag: Tag, flags: Flags, msg: &[u8]| {
-                if tag == LLMP_TAG_EVENT_TO_BOTH {
-                    let compressed;
-
-                    let event_bytes = if flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
-                        compressed = compressor.decompress(msg)?;
-                        &compressed
-                    } else {
-                        msg
+                // nn service ticks on a timer, stats are fresh whether fuzzers report or not
+                if tag == LLMP_TAG_NN_STATS_TICK {
+                    stats.publish(monitor);
+                    Ok(LlmpMsgHookResult::Handled)
+                } else if tag == LLMP_TAG_EVENT_TO_BOTH {
+                    let event: Event<I> = match decode_event(compressor, flags, msg) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            // one bad client must not take the broker down
+                            eprintln!("NN broker: dropped event of client {client_id}: {e}");
+                            return Ok(LlmpMsgHookResult::Handled);
+                        }
                     };
-                    let event: Event<I> = postcard::from_bytes(event_bytes)?;
-                    match Self::handle_in_broker(monitor, client_id, &event)? {
+                    let result = Self::handle_in_broker(monitor, client_id, &event)?;
+                    match result {
                         BrokerEventResult::Forward => Ok(LlmpMsgHookResult::ForwardToClients),
-                        BrokerEventResult::Handled => Ok(LlmpMsgHookResult::Handled),
+                        BrokerEventResult::Handled => {
+                            if let Some(nn_events) = nn_events {
+                                if nn_events.receiver_count() > 0 {
+                                    // nn connections route replies to the nn asked for them
+                                    let flags = if is_nn_reply(&event) {
+                                        flags | LLMP_FLAG_NN_REPLY
+                                    } else {
+                                        flags
+                                    };
+                                    // no one listens only if all nn are gone, nothing to report
+                                    let _ = nn_events.send(TcpRemoteNewMessage {
+                                        client_id,
+                                        tag,
+                                        flags,
+                                        payload: msg.to_vec(),
+                                    });
+                                }
+                            }
+                            Ok(LlmpMsgHookResult::Handled)
+                        }
                     }
                 } else {
                     Ok(LlmpMsgHookResult::ForwardToClients)
@@ -478,8 +529,31 @@ where
                 println!("[LOG {severity_level}]: {message}");
                 Ok(BrokerEventResult::Handled)
             }
+            // other fuzzers have nothing to do with replies to nn
+            Event::CustomBuf { .. } if is_nn_reply(event) => Ok(BrokerEventResult::Handled),
             Event::CustomBuf { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
     }
 }
+
+/// Reply addressed to one nn, see [`crate::connector::messages::NnReward`]
+/// and [`crate::connector::messages::NnControlAck`]
+fn is_nn_reply<I: Input>(event: &Event<I>) -> bool {
+    matches!(
+        event,
+        Event::CustomBuf { tag, .. } if tag == NN_REWARD_TAG || tag == NN_CONTROL_ACK_TAG
+    )
+}
+
+fn decode_event<I: Input>(
+    compressor: &GzipCompressor,
+    flags: Flags,
+    msg: &[u8],
+) -> Result<Event<I>, Error> {
+    if flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+        Ok(postcard::from_bytes(&compressor.decompress(msg)?)?)
+    } else {
+        Ok(postcard::from_bytes(msg)?)
+    }
+}
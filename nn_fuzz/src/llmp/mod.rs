@@ -13,6 +13,7 @@ use serde::de::DeserializeOwned;
 use typed_builder::TypedBuilder;
 
 use self::extention::{LlmpNnEventBroker, RestartingNnEventManager};
+use crate::connector::server::ServiceOptions;
 
 /// The llmp connection from the actual fuzzer to the process supervising it
 const _ENV_FUZZER_SENDER: &str = "_AFL_ENV_FUZZER_SENDER";
@@ -40,9 +41,8 @@ where
     broker_port: u16,
     /// Spawn nn server
     spawn_nn_client: bool,
-    /// The neural network port to use
-    #[builder(default = 7878_u16)]
-    remote_nn_port: u16,
+    /// Settings of the neural network service, the port included
+    nn_service: ServiceOptions,
 
     #[builder(setter(skip), default = PhantomData)]
     phantom_data: PhantomData<S>,
@@ -57,10 +57,11 @@ where
     /// Launch the restarting manager
     pub fn launch(&mut self) -> Result<(Option<S>, RestartingNnEventManager<S, SP>), Error> {
         // We start ourself as child process to actually fuzz
-        let broker_things = |mut broker: LlmpNnEventBroker<S::Input, MT, SP>, remote_nn_port| {
-            if let Some(nn_port) = remote_nn_port {
-                println!("B2b: Connecting to {:?}", &nn_port);
-                broker.spawn_client(nn_port);
+        let broker_things = |mut broker: LlmpNnEventBroker<S::Input, MT, SP>,
+                             nn_service: Option<ServiceOptions>| {
+            if let Some(nn_service) = nn_service {
+                println!("B2b: Connecting to {:?}", &nn_service.port);
+                broker.spawn_client(nn_service);
             };
 
             broker.broker_loop()
@@ -75,7 +76,7 @@ where
         broker_things(
             event_broker,
             if self.spawn_nn_client {
-                Some(self.remote_nn_port)
+                Some(self.nn_service.clone())
             } else {
                 None
             },